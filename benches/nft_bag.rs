@@ -0,0 +1,76 @@
+// run by `cargo bench --bench nft_bag`, which times multiset operations on nft collections of tens of thousands nfts
+use kabletop_ckb_sdk::ckb::transaction::helper::{
+    blake160, blake160_intersect, NftBag, NftInventory
+};
+use ckb_types::{
+    prelude::*, core::Capacity, packed::{
+        CellOutput, OutPoint, Script
+    }
+};
+use std::time::{
+    Duration, Instant
+};
+
+const ROUNDS: u32 = 10;
+
+// make [size] nfts out of [size / 4] kinds so that duplicates are common as real collections
+fn nfts(size: usize, seed: u8) -> Vec<[u8; 20]> {
+    (0..size)
+        .map(|i| blake160(&[&[seed][..], &((i % (size / 4)) as u64).to_le_bytes()[..]].concat()))
+        .collect()
+}
+
+// put [nfts] into an inventory of cells holding 10 nfts each
+fn inventory(nfts: &[[u8; 20]]) -> NftInventory {
+    let output = CellOutput::new_builder()
+        .type_(Some(Script::default()).pack())
+        .capacity(Capacity::bytes(300).unwrap().pack())
+        .build();
+    let mut inventory = NftInventory::new();
+    for (i, cell_nfts) in nfts.chunks(10).enumerate() {
+        let mut tx_hash = [0u8; 32];
+        tx_hash[..8].copy_from_slice(&(i as u64).to_le_bytes());
+        let out_point = OutPoint::new(tx_hash.pack(), 0);
+        inventory.insert_cell(out_point, &output, &cell_nfts.concat());
+    }
+    inventory
+}
+
+fn bench<T>(name: &str, size: usize, mut f: impl FnMut() -> T) {
+    let mut total = Duration::default();
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        std::hint::black_box(f());
+        total += start.elapsed();
+    }
+    println!("{:<28} {:>8} nfts: {:>12?} / iter", name, size, total / ROUNDS);
+}
+
+fn main() {
+    for &size in &[10_000, 50_000] {
+        let nfts1 = nfts(size, 0);
+        let nfts2 = [&nfts(size / 2, 0)[..], &nfts(size / 2, 1)[..]].concat();
+        let bag1 = NftBag::from(&nfts1[..]);
+        let bag2 = NftBag::from(&nfts2[..]);
+        bench("NftBag::from", size, || NftBag::from(&nfts1[..]));
+        bench("NftBag::intersect", size, || bag1.intersect(&bag2));
+        bench("NftBag::subtract", size, || bag1.subtract(&bag2));
+        bench("NftBag::is_subset", size, || bag2.is_subset(&bag1));
+        bench("blake160_intersect", size, || {
+            let (mut nfts1, mut nfts2) = (nfts1.clone(), nfts2.clone());
+            blake160_intersect(&mut nfts1, &mut nfts2)
+        });
+        let inventory1 = inventory(&nfts1);
+        let inventory2 = inventory(&nfts1[..size / 2]);
+        let required = nfts1.iter().step_by(size / 100).cloned().collect::<Vec<_>>();
+        bench("NftInventory::insert_cell", size, || inventory(&nfts1));
+        bench("NftInventory::cells", size, || inventory1.cells());
+        bench("NftInventory::select_cells", size, || inventory1.select_cells(&required));
+        bench("NftInventory::difference", size, || inventory1.difference(&inventory2));
+
+        // a few kinds of nfts owned many times each, where location lookups of one nft dominate
+        let dups = (0..size).map(|i| blake160(&[(i % 10) as u8])).collect::<Vec<_>>();
+        let (dups1, dups2) = (inventory(&dups), inventory(&dups[..size / 2]));
+        bench("NftInventory::difference dups", size, || dups1.difference(&dups2));
+    }
+}
@@ -3,7 +3,7 @@ use async_jsonrpc_client::{
 };
 use ckb_types::{
     prelude::*, H256, core::{
-		BlockView, Capacity
+		BlockView, Capacity, HeaderView
 	}, packed::{
         Block, Transaction, Byte32, Script
     }
@@ -11,23 +11,27 @@ use ckb_types::{
 use serde_json::{
     from_value, json
 };
-use anyhow::{
-    Result, anyhow
-};
 use crate::{
-    config::VARS as _C, ckb::{
-		transaction::helper::sighash_script, rpc::types::{
+    config::VARS as _C, error::{
+		Result, KabletopError
+	}, ckb::{
+		transaction::helper::{
+			sighash_script, type_id_script, wallet_script, payment_script, nft_script, payment_series, sale_script, NFTConfig, NftInventory, NftOffer, NftRegistry
+		}, rpc::types::{
 			Pagination, Cell, SearchKey, Order, ckb, ScriptType
 		}
 	}
 };
 use ckb_jsonrpc_types::{
-    JsonBytes, Status, Uint32, OutputsValidator
+    JsonBytes, Status, Uint32, BlockNumber, OutputsValidator
 };
 use std::{
-	sync::Mutex, collections::HashMap
+	sync::Mutex, collections::HashMap, convert::TryFrom, time::{
+		Duration, SystemTime
+	}
 };
 use ckb_sdk::rpc::HttpRpcClient;
+use async_std::task;
 
 lazy_static! {
     static ref INDEXER_CLIENT: HttpClient = HttpClient::new(_C.common.ckb_indexer_uri.as_str()).expect("indexer");
@@ -35,15 +39,15 @@ lazy_static! {
 }
 
 pub fn get_genesis_block() -> Result<Block> {
-	let mut result = Err(anyhow!("fetch genesis block failed over 5 times"));
-	for _ in 0..5 {
+	let mut result = Err(KabletopError::Rpc(String::from("fetch genesis block failed over 5 times")));
+	for attempt in 1..=5 {
 		match get_block(0) {
 			Ok(block) => {
 				result = Ok(block);
 				break
 			},
 			Err(error) => {
-				println!("{} [retry]", error);
+				tracing::warn!(%error, attempt, "fetch genesis block failed, retrying");
 				result = Err(error);
 			}
 		}
@@ -62,13 +66,27 @@ pub fn get_block(block_number: u64) -> Result<Block> {
             None
         });
     let block = {
-        let genesis = block.ok_or(anyhow!(format!("fetch block #{} error: {}", block_number, error)))?;
+        let genesis = block.ok_or_else(|| KabletopError::Rpc(format!("fetch block #{} error: {}", block_number, error)))?;
         let block: BlockView = genesis.into();
         Block::new_unchecked(block.data().as_bytes())
     };
     Ok(block)
 }
 
+pub fn get_header(block_hash: Byte32) -> Result<HeaderView> {
+	let mut error = String::new();
+    let header = CKB_CLIENT
+        .lock()
+        .unwrap()
+        .get_header(H256(block_hash.unpack()))
+        .unwrap_or_else(|err| {
+			error = err.to_string();
+            None
+        });
+    let header = header.ok_or_else(|| KabletopError::Rpc(format!("fetch header {} error: {}", block_hash, error)))?;
+    Ok(header.into())
+}
+
 pub fn get_transaction(tx_hash: Byte32) -> Result<Transaction> {
 	let mut error = String::new();
     let tx = CKB_CLIENT
@@ -79,26 +97,78 @@ pub fn get_transaction(tx_hash: Byte32) -> Result<Transaction> {
 			error = err.to_string();
             None
         });
-    let tx = tx.ok_or(anyhow!(error))?;
+    let tx = tx.ok_or(KabletopError::Rpc(error))?;
     if tx.tx_status.status == Status::Committed {
 		if let Some(transaction) = tx.transaction {
 			Ok(transaction.inner.into())
 		} else {
-			Err(anyhow!("empty transaction"))
+			Err(KabletopError::Rpc(String::from("empty transaction")))
 		}
     } else {
-        Err(anyhow!("not committed"))
+        Err(KabletopError::TransactionNotCommitted(tx_hash.to_string()))
     }
 }
 
+// get the hash of the block which has committed the transaction of [tx_hash]
+pub fn get_transaction_block_hash(tx_hash: Byte32) -> Result<Byte32> {
+	let mut error = String::new();
+    let tx = CKB_CLIENT
+        .lock()
+        .unwrap()
+        .get_transaction(H256(tx_hash.unpack()))
+        .unwrap_or_else(|err| {
+			error = err.to_string();
+            None
+        });
+    let tx = tx.ok_or(KabletopError::Rpc(error))?;
+    match (tx.tx_status.status, tx.tx_status.block_hash) {
+		(Status::Committed, Some(block_hash)) => Ok(block_hash.pack()),
+		_ => Err(KabletopError::TransactionNotCommitted(tx_hash.to_string()))
+	}
+}
+
+// poll the transaction of [tx_hash] every second until it's committed on chain or [timeout_secs] passed
+#[tracing::instrument(skip_all, fields(tx_hash = %format!("{:#x}", tx_hash)))]
+pub async fn wait_transaction_committed(tx_hash: H256, timeout_secs: u64) -> Result<Transaction> {
+	let start = SystemTime::now();
+	loop {
+		let tx = CKB_CLIENT
+			.lock()
+			.unwrap()
+			.get_transaction(tx_hash.clone())
+			.map_err(KabletopError::Rpc)?;
+		if let Some(tx) = tx {
+			match tx.tx_status.status {
+				Status::Committed => {
+					return tx.transaction
+						.map(|transaction| transaction.inner.into())
+						.ok_or_else(|| KabletopError::Rpc(String::from("empty transaction")));
+				},
+				Status::Rejected => return Err(KabletopError::TransactionRejected(format!("{:#x}", tx_hash))),
+				_ => {}
+			}
+		}
+		if SystemTime::now().duration_since(start).unwrap_or_default() > Duration::from_secs(timeout_secs) {
+			return Err(KabletopError::TransactionNotCommitted(format!("{:#x} (timeout in {} seconds)", tx_hash, timeout_secs)));
+		}
+		task::sleep(Duration::from_secs(1)).await;
+	}
+}
+
 pub fn send_transaction(tx: Transaction) -> Result<H256> {
     let result = CKB_CLIENT
         .lock()
         .unwrap()
 		.send_transaction(tx, Some(OutputsValidator::Passthrough));
 	match result {
-		Ok(hash) => Ok(hash),
-		Err(err) => Err(anyhow!(err))
+		Ok(hash) => {
+			tracing::info!(tx_hash = %format!("{:#x}", hash), "transaction sent");
+			Ok(hash)
+		},
+		Err(err) => {
+			tracing::warn!(%err, "send transaction failed");
+			Err(KabletopError::Rpc(err))
+		}
 	}
 }
 
@@ -109,17 +179,59 @@ pub fn get_tip_block_number() -> Result<u64> {
         .get_tip_block_number();
 	match result {
 		Ok(number) => Ok(number),
-		Err(err)   => Err(anyhow!(err))
+		Err(err)   => Err(KabletopError::Rpc(err))
 	}
 }
 
+// wait until the chain grows to [block_number] and then return the header of that block
+#[tracing::instrument]
+pub async fn wait_block_header(block_number: u64, timeout_secs: u64) -> Result<HeaderView> {
+	let start = SystemTime::now();
+	while get_tip_block_number()? < block_number {
+		if SystemTime::now().duration_since(start).unwrap_or_default() > Duration::from_secs(timeout_secs) {
+			return Err(KabletopError::BlockNotReached(format!("#{} (timeout in {} seconds)", block_number, timeout_secs)));
+		}
+		task::sleep(Duration::from_secs(1)).await;
+	}
+	Ok(get_block(block_number)?.into_view().header())
+}
+
+// the number of the tip block which ckb-indexer has synced to
+pub async fn get_indexer_tip_block_number() -> Result<u64> {
+	let output = INDEXER_CLIENT
+		.request("get_tip", None)
+		.await
+		.map_err(|err| KabletopError::Rpc(err.to_string()))?;
+	match output {
+		Output::Success(value) => {
+			let number: BlockNumber = from_value(value.result["block_number"].clone())?;
+			Ok(number.value())
+		},
+		Output::Failure(err) => Err(KabletopError::Rpc(err.to_string()))
+	}
+}
+
+// wait until ckb-indexer has synced to [block_number], so that live cells searched afterwards reflect the txs committed
+// in or before that block
+#[tracing::instrument]
+pub async fn wait_indexer_synced(block_number: u64, timeout_secs: u64) -> Result<()> {
+	let start = SystemTime::now();
+	while get_indexer_tip_block_number().await? < block_number {
+		if SystemTime::now().duration_since(start).unwrap_or_default() > Duration::from_secs(timeout_secs) {
+			return Err(KabletopError::BlockNotReached(format!("indexer #{} (timeout in {} seconds)", block_number, timeout_secs)));
+		}
+		task::sleep(Duration::from_secs(1)).await;
+	}
+	Ok(())
+}
+
 pub async fn get_live_cells(search_key: SearchKey, limit: u32, cursor: Option<JsonBytes>) -> Result<Pagination<ckb::Cell>> {
     let output = INDEXER_CLIENT.request("get_cells", Some(Params::Array(vec![
         json!(search_key),
         json!(Order::Asc),
         json!(Uint32::from(limit)),
         json!(cursor)
-    ]))).await?;
+    ]))).await.map_err(|err| KabletopError::Rpc(err.to_string()))?;
     match output {
         Output::Success(value) => {
             let pagination: Pagination<Cell> = from_value(value.result)?;
@@ -134,14 +246,38 @@ pub async fn get_live_cells(search_key: SearchKey, limit: u32, cursor: Option<Js
             };
             Ok(pagination)
         },
-        Output::Failure(err) => Err(anyhow!(err))
+        Output::Failure(err) => Err(KabletopError::Rpc(err.to_string()))
     }
 }
 
+// search live cells whose lock and type scripts are both exactly [lock_script] and [type_script], the indexer matches
+// script args by prefix, so cells with longer args like other nft series must be filtered out
+pub async fn get_exact_live_cells(lock_script: Script, type_script: Script, limit: u32) -> Result<Vec<ckb::Cell>> {
+	let mut cursor = None;
+	let mut cells = vec![];
+	loop {
+		let search_key = SearchKey::new(lock_script.clone().into(), ScriptType::Lock).filter(type_script.clone().into());
+		let live_cells = get_live_cells(search_key, 20, cursor).await?;
+		live_cells.objects
+			.into_iter()
+			.filter(|cell| {
+				cell.output.lock().as_slice() == lock_script.as_slice()
+					&& cell.output.type_().to_opt().map(|script| script.as_slice() == type_script.as_slice()) == Some(true)
+			})
+			.for_each(|cell| cells.push(cell));
+		if cells.len() >= limit as usize || live_cells.last_cursor.is_empty() {
+			break;
+		}
+		cursor = Some(live_cells.last_cursor);
+	}
+	cells.truncate(limit as usize);
+	Ok(cells)
+}
+
 pub async fn get_total_capacity(lock_args: Vec<u8>) -> Result<Capacity> {
     let mut cursor = None;
 	let mut total_capacity = 0u64;
-	let lock_script = sighash_script(lock_args.as_slice());
+	let lock_script = sighash_script(lock_args.as_slice())?;
     loop {
 		let search_key = SearchKey::new(lock_script.clone().into(), ScriptType::Lock);
 		let live_cells = get_live_cells(search_key, 10, cursor).await?;
@@ -161,35 +297,173 @@ pub async fn get_total_capacity(lock_args: Vec<u8>) -> Result<Capacity> {
 	Ok(Capacity::shannons(total_capacity))
 }
 
-pub async fn get_live_nfts(lock_script: Script, type_script: Option<Script>, cellstep: u32) -> Result<HashMap<[u8; 20], u32>> {
+// search live nft cells owned by [lock_script] and filtered by [type_script] into an inventory, which keeps where each
+// nft lives, cells without type script are left out
+pub async fn get_nft_inventory(lock_script: Script, type_script: Option<Script>, cellstep: u32) -> Result<NftInventory> {
     let mut cursor = None;
-	let mut live_nfts = HashMap::new();
+	let mut inventory = NftInventory::new();
     loop {
 		let mut search_key = SearchKey::new(lock_script.clone().into(), ScriptType::Lock);
 		if let Some(type_script) = &type_script {
 			search_key = search_key.filter(type_script.clone().into());
 		}
 		let live_cells = get_live_cells(search_key, cellstep, cursor).await?;
-		live_cells.objects
-			.iter()
-			.for_each(|cell| {
-				let mut data = cell.output_data.to_vec();
-				let mut nft = [0u8; 20];
-				let n = data.len() / 20;
-				for _ in 0..n {
-					nft.copy_from_slice(&data[..20]);
-					data = data[20..].to_vec();
-					if let Some(count) = live_nfts.get_mut(&nft) {
-						*count += 1;
-					} else {
-						live_nfts.insert(nft, 1);
-					}
-				}
-			});
+		for cell in live_cells.objects {
+			inventory.insert_cell(cell.out_point, &cell.output, &cell.output_data);
+		}
         if live_cells.last_cursor.is_empty() {
             break;
         } 
         cursor = Some(live_cells.last_cursor);
 	}
-	Ok(live_nfts)
+	Ok(inventory)
+}
+
+// the same as get_nft_inventory but searches nft cells under each of [lock_scripts], which helps to collect nfts owned
+// by several locks of one owner, e.g. sighash, multisig or anyone-can-pay
+pub async fn get_nft_inventory_by_locks(
+	lock_scripts: Vec<Script>, type_script: Option<Script>, cellstep: u32
+) -> Result<NftInventory> {
+	let mut inventory = NftInventory::new();
+	for lock_script in lock_scripts {
+		let lock_inventory = get_nft_inventory(lock_script, type_script.clone(), cellstep).await?;
+		inventory = inventory.union(&lock_inventory);
+	}
+	Ok(inventory)
+}
+
+// the same as get_nft_inventory_by_locks but stops paging once the inventory covers all [required_nfts], which saves
+// searching through the whole collection when only a few nfts are needed
+pub async fn get_nft_inventory_covering(
+	lock_scripts: Vec<Script>, type_script: Option<Script>, required_nfts: &[[u8; 20]], cellstep: u32
+) -> Result<NftInventory> {
+	let mut inventory = NftInventory::new();
+	for lock_script in lock_scripts {
+		let mut cursor = None;
+		loop {
+			if inventory.contains_all(required_nfts) {
+				return Ok(inventory);
+			}
+			let mut search_key = SearchKey::new(lock_script.clone().into(), ScriptType::Lock);
+			if let Some(type_script) = &type_script {
+				search_key = search_key.filter(type_script.clone().into());
+			}
+			let live_cells = get_live_cells(search_key, cellstep, cursor).await?;
+			for cell in live_cells.objects {
+				inventory.insert_cell(cell.out_point, &cell.output, &cell.output_data);
+			}
+			if live_cells.last_cursor.is_empty() {
+				break;
+			}
+			cursor = Some(live_cells.last_cursor);
+		}
+	}
+	Ok(inventory)
+}
+
+// search open sale offers of nfts composed by [composer_pkhash], the indexer matches lock args by prefix, so all sale
+// cells are searched if [seller_lock] is None, otherwise only the offers listed by [seller_lock]
+pub async fn get_nft_offers(
+	composer_pkhash: [u8; 20], seller_lock: Option<Script>, cellstep: u32
+) -> Result<Vec<NftOffer>> {
+	let sale_script = sale_script(seller_lock.map(|lock| lock.as_slice().to_vec()).unwrap_or_default())?;
+	let nft_script = nft_script(wallet_script(composer_pkhash.to_vec()).calc_script_hash().raw_data().to_vec());
+	let mut cursor = None;
+	let mut offers = vec![];
+	loop {
+		let search_key = SearchKey::new(sale_script.clone().into(), ScriptType::Lock).filter(nft_script.clone().into());
+		let live_cells = get_live_cells(search_key, cellstep, cursor).await?;
+		for cell in live_cells.objects {
+			if let Some(offer) = NftOffer::from_cell(cell.out_point, cell.output, &cell.output_data) {
+				offers.push(offer);
+			}
+		}
+		if live_cells.last_cursor.is_empty() {
+			break;
+		}
+		cursor = Some(live_cells.last_cursor);
+	}
+	Ok(offers)
+}
+
+pub async fn get_live_nfts(lock_script: Script, type_script: Option<Script>, cellstep: u32) -> Result<HashMap<[u8; 20], u32>> {
+	Ok(get_nft_inventory(lock_script, type_script, cellstep).await?.counts())
+}
+
+// search config cells of all series of composer [composer_pkhash] in ASC order of series, the cells which can't be
+// parsed into NFTConfig are skipped
+pub async fn get_nft_configs(composer_pkhash: [u8; 20], cellstep: u32) -> Result<Vec<(u8, NFTConfig)>> {
+	let wallet_script = wallet_script(composer_pkhash.to_vec());
+	let payment_script = payment_script(composer_pkhash.to_vec());
+	let mut configs = vec![];
+	let mut cursor = None;
+	loop {
+		let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock).filter(payment_script.clone().into());
+		let live_cells = get_live_cells(search_key, cellstep, cursor).await?;
+		for cell in live_cells.objects {
+			if let Some(script) = cell.output.type_().to_opt() {
+				let args = script.args().raw_data();
+				if args.len() <= 21 && args[..20] == composer_pkhash[..] {
+					// one malformed config cell shouldn't hide the other series of composer
+					match NFTConfig::try_from(cell.output_data) {
+						Ok(config) => configs.push((payment_series(&args), config)),
+						Err(err)   => tracing::warn!(%err, out_point = %cell.out_point, "skip malformed nft config cell")
+					}
+				}
+			}
+		}
+		if live_cells.last_cursor.is_empty() {
+			break;
+		}
+		cursor = Some(live_cells.last_cursor);
+	}
+	configs.sort_by_key(|(series, _)| *series);
+	Ok(configs)
+}
+
+// load nft metadata registry from the metadata cell with type-id [type_args] published by composer [composer_pkhash],
+// the registry is checked against config tables of all series
+pub async fn get_nft_registry(composer_pkhash: [u8; 20], type_args: [u8; 32], cellstep: u32) -> Result<NftRegistry> {
+	let search_key = SearchKey::new(type_id_script(type_args.to_vec()).into(), ScriptType::Type);
+	let metadata_cell = get_live_cells(search_key, 1, None)
+		.await?
+		.objects
+		.pop()
+		.ok_or_else(|| KabletopError::CellNotFound(format!("metadata cell with type-id {}", hex::encode(type_args))))?;
+	if metadata_cell.output.lock() != sighash_script(&composer_pkhash[..])? {
+		return Err(KabletopError::NftMetadata(String::from("metadata cell isn't published by composer")));
+	}
+	let registry = NftRegistry::from_json(&metadata_cell.output_data)?;
+	let nfts = get_nft_configs(composer_pkhash, cellstep)
+		.await?
+		.into_iter()
+		.flat_map(|(_, config)| config.nfts())
+		.collect::<Vec<_>>();
+	registry.check(&nfts)?;
+	Ok(registry)
+}
+
+// search live nfts owned by [lock_script] and group them by the series of composer [composer_pkhash], every nft is put
+// into the first series whose config cell contains it, and nfts which are not in any series are left out
+pub async fn get_live_nfts_by_series(
+	lock_script: Script, composer_pkhash: [u8; 20], cellstep: u32
+) -> Result<HashMap<u8, HashMap<[u8; 20], u32>>> {
+	let wallet_script = wallet_script(composer_pkhash.to_vec());
+	let nft_script = nft_script(wallet_script.calc_script_hash().raw_data().to_vec());
+
+	// collect nft tables of all series
+	let series_nfts = get_nft_configs(composer_pkhash, cellstep)
+		.await?
+		.into_iter()
+		.map(|(series, config)| (series, config.nfts()))
+		.collect::<Vec<_>>();
+
+	// group owned nfts
+	let mut grouped_nfts: HashMap<u8, HashMap<[u8; 20], u32>> = HashMap::new();
+	for (nft, count) in get_live_nfts(lock_script, Some(nft_script), cellstep).await? {
+		if let Some((series, _)) = series_nfts.iter().find(|(_, nfts)| nfts.contains(&nft)) {
+			grouped_nfts.entry(*series).or_default().insert(nft, count);
+		}
+	}
+	Ok(grouped_nfts)
 }
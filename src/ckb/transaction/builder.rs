@@ -1,12 +1,16 @@
 use ckb_types::{
-    prelude::*, bytes::Bytes, core::{
+    prelude::*, bytes::Bytes, H256, core::{
         TransactionBuilder, TransactionView, Capacity, ScriptHashType
     }, packed::{
         CellOutput, CellInput, OutPoint, Script, WitnessArgs, Byte32
     }
 };
 use crate::{
-    config::VARS as _C, ckb::{
+    config::{
+        VARS as _C, types::ckb as conf
+    }, error::{
+        Result, KabletopError
+    }, ckb::{
         transaction::{
             helper, channel::protocol
         }, rpc::{
@@ -18,42 +22,68 @@ use crate::{
         }
     }
 };
-use anyhow::{
-    Result, anyhow
-};
 use molecule::{
     prelude::Entity as MolEntity
 };
-use ckb_crypto::secp::Signature;
+use ckb_crypto::secp::{
+    Signature, Privkey
+};
 use ckb_hash::new_blake2b;
 use molecule::prelude::Builder as MolBuilder;
+use std::{
+    convert::TryFrom, collections::{
+        HashMap, VecDeque, BTreeSet
+    }
+};
+
+// the max serialized size of a transaction which ckb tx-pool accepts, and the size reserved for cell deps, outputs,
+// witnesses and sighash cells when building a large transaction
+const MAX_TX_SIZE: usize = 512_000;
+const TX_SIZE_RESERVED: usize = 16_000;
 
 /* CONFIG_CELL
 *
-* to help nft composers compose their own NFTs config cell, target output cell should only have one in CKB,
-* so to create another NFTs config cell will consume the previous one
+* to help nft composers compose their own NFTs config cell, target output cell should only have one for each series
+* in CKB, so to create another NFTs config cell in the same [series] will consume the previous one, and the series
+* byte is omitted from payment args for the legacy series 0
 *
-* data:
-*     ckb_per_package(u64) | nft_count_per_package(u8) | [blake160|rate(u16)] | [blake160|rate(u16)] | ...
+* data (legacy):
+*     ckb_per_package(u64) | nft_count_per_package(u8) | [blake160|rate(u8)] | [blake160|rate(u8)] | ...
+* data (v2):
+*     ckb_per_package(u64) | 0(u8) | 2(u8) | nft_count_per_package(u8) | [blake160|rate(u16)] | [blake160|rate(u16)] | ...
+*
+* NOTE: the v2 format only works with the nft_payment_contract and nft_contract which read the version marker and u16
+* rates, older ones take the marker as nft_count_per_package and the rates as u8, so both contract entries of
+* "Kabletop.toml" must declare feature "config_v2" to compose a v2 config, otherwise FeatureMissing returns
 * lock:
 *     code_hash = nft_wallet_contract 
-*     hash_type = data
+*     hash_type = data | data1 | type
 *     args 	    = composer_pubkey_blake160
 * type:
 *     code_hash = nft_payment_contract 
-*     hash_type = data
-*     args 	    = composer_pubkey_blake160
+*     hash_type = data | data1 | type
+*     args 	    = composer_pubkey_blake160 | series (uint8)
 */
 pub async fn build_tx_compose_nft(
-    package_price: u64, package_capacity: u8, nft_table: Vec<([u8; 20], u8)>
+    series: u8, package_price: u64, package_capacity: u8, nft_table: Vec<([u8; 20], u8)>
 ) -> Result<TransactionView> {
+    build_tx_compose_nft_config(series, helper::NFTConfig::try_new(package_price, package_capacity, nft_table)?).await
+}
+
+// the same as build_tx_compose_nft but with a prepared [nft_config], which enables composing in v2 format
+#[tracing::instrument(skip(nft_config), fields(nfts = nft_config.nfts().len(), version = ?nft_config.version()))]
+pub async fn build_tx_compose_nft_config(series: u8, nft_config: helper::NFTConfig) -> Result<TransactionView> {
+    if nft_config.version() != helper::NFTConfigVersion::Legacy {
+        _C.payment.require("payment", conf::CONFIG_V2)?;
+        _C.nft.require("nft", conf::CONFIG_V2)?;
+    }
+
     // prepare scripts
     let wallet_script = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
-    let payment_script = helper::payment_script(keystore::COMPOSER_PUBHASH.to_vec());
+    let payment_script = helper::payment_script(helper::payment_args(&keystore::COMPOSER_PUBHASH, series)?);
 
     // prepare input cell
-    let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock).filter(payment_script.clone().into());
-    let inputs = rpc::get_live_cells(search_key, 1, None).await?.objects
+    let inputs = rpc::get_exact_live_cells(wallet_script.clone(), payment_script.clone(), 1).await?
         .iter()
         .map(|cell| {
             CellInput::new_builder()
@@ -63,7 +93,7 @@ pub async fn build_tx_compose_nft(
         .collect::<Vec<_>>();
 
     // prepare output data
-    let output_data = helper::NFTConfig::new(package_price, package_capacity, nft_table).to_ckb_bytes();
+    let output_data = nft_config.to_ckb_bytes();
 
     // prepare output cell
     let output = CellOutput::new_builder()
@@ -80,8 +110,78 @@ pub async fn build_tx_compose_nft(
 
     // complete tx
     let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::COMPOSER_PUBHASH, helper::fee("0.1")).await?;
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.payment.tx_hash.clone(), 0));
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.wallet.tx_hash.clone(), 0));
+    let tx = helper::add_contract_celldep(tx, &_C.payment).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.wallet).await?;
+
+    // sign tx
+    let tx = signer::sign(tx, &keystore::COMPOSER_PRIVKEY, vec![], Box::new(|_| true));
+    Ok(tx)
+}
+
+/* METADATA_CELL
+*
+* to help nft composers publish the metadata registry of their NFTs in json manifest format (see [helper::NftRegistry]),
+* the cell is identified by type-id, so it creates a new metadata cell if [type_args] is None, otherwise the previous
+* metadata cell with [type_args] is consumed and replaced
+*
+* data:
+*     json manifest
+* lock:
+*     code_hash = sighash_blake160
+*     hash_type = type
+*     args      = composer_pubkey_blake160
+* type:
+*     code_hash = type_id
+*     hash_type = type
+*     args      = blake2b_256(first_input | output_index(u64))
+*/
+#[tracing::instrument(skip(registry), fields(nfts = registry.len()))]
+pub async fn build_tx_publish_nft_metadata(registry: &helper::NftRegistry, type_args: Option<[u8; 32]>) -> Result<TransactionView> {
+    // prepare input cell
+    let output_data = Bytes::from(registry.to_json()?);
+    let mut inputs = vec![];
+    if let Some(type_args) = type_args {
+        let search_key = SearchKey::new(helper::type_id_script(type_args.to_vec()).into(), ScriptType::Type);
+        let metadata_cell = rpc::get_live_cells(search_key, 1, None)
+            .await?
+            .objects
+            .pop()
+            .ok_or_else(|| KabletopError::CellNotFound(format!("metadata cell with type-id {}", hex::encode(type_args))))?;
+        inputs.push(CellInput::new(metadata_cell.out_point, 0));
+    }
+
+    // prepare output cell with a placeholder type-id args if it's a new one
+    let output = CellOutput::new_builder()
+        .lock(helper::sighash_script(&keystore::COMPOSER_PUBHASH[..])?)
+        .type_(Some(helper::type_id_script(type_args.unwrap_or([0u8; 32]).to_vec())).pack())
+        .build_exact_capacity(Capacity::bytes(output_data.len())?)?;
+
+    // prepare tx
+    let tx = TransactionBuilder::default()
+        .inputs(inputs)
+        .output(output)
+        .output_data(output_data.pack())
+        .build();
+
+    // complete tx and fill the real type-id args
+    let mut tx = helper::complete_tx_with_sighash_cells(tx, &keystore::COMPOSER_PUBHASH, helper::fee("0.1")).await?;
+    if type_args.is_none() {
+        let first_input = tx
+            .inputs()
+            .get(0)
+            .ok_or_else(|| KabletopError::InvalidTransaction(String::from("metadata tx has no inputs")))?;
+        let type_args = helper::calc_type_id_args(&first_input, 0);
+        let mut outputs = tx.outputs().into_iter().collect::<Vec<_>>();
+        outputs[0] = outputs[0]
+            .clone()
+            .as_builder()
+            .type_(Some(helper::type_id_script(type_args.to_vec())).pack())
+            .build();
+        tx = tx
+            .as_advanced_builder()
+            .set_outputs(outputs)
+            .build();
+    }
 
     // sign tx
     let tx = signer::sign(tx, &keystore::COMPOSER_PRIVKEY, vec![], Box::new(|_| true));
@@ -99,34 +199,31 @@ pub async fn build_tx_compose_nft(
 * 	  0 (uint8)
 * lock:
 * 	  code_hash = nft_wallet_contract 
-* 	  hash_type = data
+* 	  hash_type = data | data1 | type
 * 	  args 	    = composer_pubkey_blake160
 * type:
 * 	  code_hash = nft_payment_contract 
-* 	  hash_type = data
-* 	  args 	    = user_pubkey_blake160
+* 	  hash_type = data | data1 | type
+* 	  args 	    = user_pubkey_blake160 | series (uint8)
 * capacity:
 * 	  any
 */
-pub async fn build_tx_create_nft_store() -> Result<TransactionView> {
+#[tracing::instrument]
+pub async fn build_tx_create_nft_store(series: u8) -> Result<TransactionView> {
     // prepare scripts
     let wallet_script           = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
-    let composer_payment_script = helper::payment_script(keystore::COMPOSER_PUBHASH.to_vec());
-    let user_payment_script     = helper::payment_script(keystore::USER_PUBHASH.to_vec());
+    let composer_payment_script = helper::payment_script(helper::payment_args(&keystore::COMPOSER_PUBHASH, series)?);
+    let user_payment_script     = helper::payment_script(helper::payment_args(&keystore::USER_PUBHASH, series)?);
 
     // check composer if has composed nft or not
-    let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock)
-        .filter(composer_payment_script.into());
-    let config_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
+    let config_cell = rpc::get_exact_live_cells(wallet_script.clone(), composer_payment_script, 1).await?;
     if config_cell.is_empty() {
-        return Err(anyhow!("composer hasn't composed any NFTs yet."));
+        return Err(KabletopError::ComposerNotFound);
     }
 
     // check user if has created a nft store
-    let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock)
-        .filter(user_payment_script.clone().into());
-    if !rpc::get_live_cells(search_key, 1, None).await?.objects.is_empty() {
-        return Err(anyhow!("user has already created this NFT store."));
+    if !rpc::get_exact_live_cells(wallet_script.clone(), user_payment_script.clone(), 1).await?.is_empty() {
+        return Err(KabletopError::StoreAlreadyExists);
     }
 
     // prepare output data
@@ -146,8 +243,8 @@ pub async fn build_tx_create_nft_store() -> Result<TransactionView> {
 
     // complete tx
     let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::USER_PUBHASH, helper::fee("0.1")).await?;
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.payment.tx_hash.clone(), 0));
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.wallet.tx_hash.clone(), 0));
+    let tx = helper::add_contract_celldep(tx, &_C.payment).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.wallet).await?;
     let tx = helper::add_code_celldep(tx, config_cell[0].out_point.clone());
 
     // sign tx
@@ -158,46 +255,66 @@ pub async fn build_tx_create_nft_store() -> Result<TransactionView> {
 /* PAYMENT_CELL
 *
 * to help other users custom their own wallet cell to create payment cell to buy NFT packages from corresponding
-* composer
+* composer, a commitment of user secret could be attached so that the packages can only be revealed by the header of
+* a block REVEAL_DELAY_BLOCKS after purchase mixed with the secret, which nor miners or users are able to grind
+*
+* NOTE: the commitment only works with the nft_payment_contract and nft_contract which accept 33 bytes payment data,
+* read the secret from the input_type witness of wallet_cell and check the delayed header dep against the payment block,
+* REVEAL_DELAY_BLOCKS is only a client-side constant unless the contracts enforce the same delay, so both contract
+* entries of "Kabletop.toml" must declare feature "commit_reveal" for such contracts, otherwise the commitment builders
+* return FeatureMissing before building
 *
 * celldeps：
 * 	  config_cell
 * data:
-* 	  nft_package_count (uint8)
+* 	  nft_package_count (uint8), or
+* 	  nft_package_count (uint8) | commitment (blake2b_256 of user secret)
 * lock:
 * 	  code_hash = nft_wallet_contract 
-* 	  hash_type = data
+* 	  hash_type = data | data1 | type
 * 	  args 	    = composer_pubkey_blake160
 * type:
 * 	  code_hash = nft_payment_contract 
-* 	  hash_type = data
-* 	args 	    = user_pubkey_blake160
+* 	  hash_type = data | data1 | type
+* 	args 	    = user_pubkey_blake160 | series (uint8)
 * capacity:
-* 	  any (must be greator than wallet_cell's)
+* 	  any (must be greator than wallet_cell's, and cover the 32 bytes of commitment if attached)
 */
-pub async fn build_tx_purchase_nft_package(package_count: u8) -> Result<TransactionView> {
+#[tracing::instrument]
+pub async fn build_tx_purchase_nft_package(series: u8, package_count: u8) -> Result<TransactionView> {
+    purchase_nft_package(series, package_count, None).await
+}
+
+// the same as build_tx_purchase_nft_package but commits to user [secret] by its [commitment], the secret must be kept
+// by user to reveal the packages with build_tx_reveal_nft_package_with_secret later
+#[tracing::instrument(skip(commitment))]
+pub async fn build_tx_purchase_nft_package_with_commitment(
+    series: u8, package_count: u8, commitment: [u8; 32]
+) -> Result<TransactionView> {
+    _C.payment.require("payment", conf::COMMIT_REVEAL)?;
+    _C.nft.require("nft", conf::COMMIT_REVEAL)?;
+    purchase_nft_package(series, package_count, Some(commitment)).await
+}
+
+async fn purchase_nft_package(series: u8, package_count: u8, commitment: Option<[u8; 32]>) -> Result<TransactionView> {
     // prepare scripts
     let wallet_script           = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
-    let composer_payment_script = helper::payment_script(keystore::COMPOSER_PUBHASH.to_vec());
-    let user_payment_script     = helper::payment_script(keystore::USER_PUBHASH.to_vec());
+    let composer_payment_script = helper::payment_script(helper::payment_args(&keystore::COMPOSER_PUBHASH, series)?);
+    let user_payment_script     = helper::payment_script(helper::payment_args(&keystore::USER_PUBHASH, series)?);
 
     // check composer if has composed nft or not
-    let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock)
-        .filter(composer_payment_script.into());
-    let config_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
+    let config_cell = rpc::get_exact_live_cells(wallet_script.clone(), composer_payment_script, 1).await?;
     if config_cell.is_empty() {
-        return Err(anyhow!("composer hasn't composed any NFTs yet."));
+        return Err(KabletopError::ComposerNotFound);
     }
 
     // check user if has created a nft store or on the right status
-    let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock)
-        .filter(user_payment_script.clone().into());
-    let wallet_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
+    let wallet_cell = rpc::get_exact_live_cells(wallet_script.clone(), user_payment_script.clone(), 1).await?;
     if wallet_cell.is_empty() {
-        return Err(anyhow!("user hasn't owned a NFT store."));
+        return Err(KabletopError::StoreNotFound);
     }
 	if wallet_cell[0].output_data.first() != Some(&0) {
-        return Err(anyhow!("NFT store's currently on reveal status."));
+        return Err(KabletopError::StorePendingReveal);
 	}
 
     // prepare input cell
@@ -206,11 +323,14 @@ pub async fn build_tx_purchase_nft_package(package_count: u8) -> Result<Transact
         .build();
 
     // parse from composed output data
-    let nft_config = helper::NFTConfig::from(config_cell[0].output_data.clone());
-    let packages_price = nft_config.buy_package(package_count as u64);
+    let nft_config = helper::NFTConfig::try_from(config_cell[0].output_data.clone())?;
+    let packages_price = nft_config.buy_package(package_count);
 
     // prepare output data
-    let output_data = vec![package_count];
+    let mut output_data = vec![package_count];
+    if let Some(commitment) = commitment {
+        output_data.append(&mut commitment.to_vec());
+    }
 
     // prepare output cell
     let mut capacity: u64 = wallet_cell[0].output.capacity().unpack();
@@ -221,6 +341,15 @@ pub async fn build_tx_purchase_nft_package(package_count: u8) -> Result<Transact
         .capacity(capacity.pack())
         .build();
 
+    // the commitment enlarges wallet cell which must be covered by the price since payment is the capacity difference
+    let occupied = output.occupied_capacity(Capacity::bytes(output_data.len())?)?.as_u64();
+    if capacity < occupied {
+        return Err(KabletopError::InsufficientCapacity {
+            required:  occupied,
+            available: capacity
+        });
+    }
+
     // prepare tx
     let tx = TransactionBuilder::default()
         .input(input)
@@ -230,8 +359,8 @@ pub async fn build_tx_purchase_nft_package(package_count: u8) -> Result<Transact
 
     // complete tx
     let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::USER_PUBHASH, helper::fee("0.1")).await?;
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.payment.tx_hash.clone(), 0));
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.wallet.tx_hash.clone(), 0));
+    let tx = helper::add_contract_celldep(tx, &_C.payment).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.wallet).await?;
     let tx = helper::add_code_celldep(tx, config_cell[0].out_point.clone());
 
     // sign tx
@@ -241,8 +370,14 @@ pub async fn build_tx_purchase_nft_package(package_count: u8) -> Result<Transact
 
 /* WALLET_CELL + NFT_CELL
 *
-* to help other users rip NFT packages they purchased before, and recover payment cell to wallet cell
+* to help other users rip NFT packages they purchased before, and recover payment cell to wallet cell, the lotteries
+* are seeded by the header of payment block, or by the mix of user secret and the header of the block REVEAL_DELAY_BLOCKS
+* after payment block if payment cell has a commitment, the revealed nfts go to [nft_lock] which could be any lock (e.g.
+* a friend's account for a gift purchase), and the tx fee and the capacity of nft_cell are paid by [fee_payer] which is
+* user by default
 *
+* witnesses:
+* 	  input_type of wallet_cell = user secret (only with commitment)
 * // OUTPUT_CELL_1 (same as wallet_cell)
 * celldeps：
 * 	  config_cell
@@ -250,50 +385,67 @@ pub async fn build_tx_purchase_nft_package(package_count: u8) -> Result<Transact
 * 	  0 (uint8)
 * lock:
 * 	  code_hash = nft_wallet_contract 
-* 	  hash_type = data
+* 	  hash_type = data | data1 | type
 * 	  args 	    = composer_pubkey_blake160
 * type:
 * 	  code_hash = nft_payment_contract 
-* 	  hash_type = data
-* 	  args 	    = user_pubkey_blake160
+* 	  hash_type = data | data1 | type
+* 	  args 	    = user_pubkey_blake160 | series (uint8)
 * capacity:
 * 	  any (must be greator than or equal to payment_cell's)
 * 
 * // OUTPUT_CELL_2 (same as nft_cell)
 * headerdeps:
 * 	  blockheader from payment_cell
+* 	  blockheader REVEAL_DELAY_BLOCKS after payment_cell (only with commitment)
 * data:
 * 	  blake160 | blake160 | ...
 * lock:
-* 	  any
+* 	  nft_lock (any), or sighash_blake160 with user_pubkey_blake160 by default
 * type:
 * 	  code_hash = nft_contract 
-* 	  hash_type = data
+* 	  hash_type = data | data1 | type
 * 	  args 	    = nft_wallet_lockhash
 */
-pub async fn build_tx_reveal_nft_package() -> Result<TransactionView> {
+#[tracing::instrument(skip(nft_lock, fee_payer))]
+pub async fn build_tx_reveal_nft_package(
+    series: u8, nft_lock: Option<Script>, fee_payer: Option<&Privkey>
+) -> Result<TransactionView> {
+    reveal_nft_package(series, None, nft_lock, fee_payer).await
+}
+
+// the same as build_tx_reveal_nft_package but for payment cell with a commitment, which reveals with user [secret] and
+// blocks until the required block is reached or [timeout_secs] passes, see the NOTE of PAYMENT_CELL for contracts
+#[tracing::instrument(skip(secret, nft_lock, fee_payer))]
+pub async fn build_tx_reveal_nft_package_with_secret(
+    series: u8, secret: [u8; 32], nft_lock: Option<Script>, fee_payer: Option<&Privkey>, timeout_secs: u64
+) -> Result<TransactionView> {
+    _C.payment.require("payment", conf::COMMIT_REVEAL)?;
+    _C.nft.require("nft", conf::COMMIT_REVEAL)?;
+    reveal_nft_package(series, Some((secret, timeout_secs)), nft_lock, fee_payer).await
+}
+
+async fn reveal_nft_package(
+    series: u8, reveal_secret: Option<([u8; 32], u64)>, nft_lock: Option<Script>, fee_payer: Option<&Privkey>
+) -> Result<TransactionView> {
     // prepare scripts
     let wallet_script           = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
     let nft_script              = helper::nft_script(wallet_script.calc_script_hash().raw_data().to_vec());
-    let composer_payment_script = helper::payment_script(keystore::COMPOSER_PUBHASH.to_vec());
-    let user_payment_script     = helper::payment_script(keystore::USER_PUBHASH.to_vec());
+    let composer_payment_script = helper::payment_script(helper::payment_args(&keystore::COMPOSER_PUBHASH, series)?);
+    let user_payment_script     = helper::payment_script(helper::payment_args(&keystore::USER_PUBHASH, series)?);
 
     // check composer if has composed nft or not
-    let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock)
-        .filter(composer_payment_script.into());
-    let config_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
+    let config_cell = rpc::get_exact_live_cells(wallet_script.clone(), composer_payment_script, 1).await?;
     if config_cell.is_empty() {
-        return Err(anyhow!("composer hasn't composed any NFTs yet."));
+        return Err(KabletopError::ComposerNotFound);
     }
 
     // check user if has created a nft store
-    let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock)
-        .filter(user_payment_script.clone().into());
-    let wallet_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
+    let wallet_cell = rpc::get_exact_live_cells(wallet_script.clone(), user_payment_script.clone(), 1).await?;
     if wallet_cell.is_empty() 
         || wallet_cell[0].output_data.first() == None
         || wallet_cell[0].output_data.first() == Some(&0) {
-        return Err(anyhow!("user hasn't owned a NFT payment certificate."));
+        return Err(KabletopError::PaymentNotFound);
     }
 
     // prepare input cell
@@ -301,12 +453,33 @@ pub async fn build_tx_reveal_nft_package() -> Result<TransactionView> {
         .previous_output(wallet_cell[0].out_point.clone())
         .build();
 
+    // prepare lottery seed, the secret must match the commitment and the delayed block must be reached
+    let payment_header = rpc::get_block(wallet_cell[0].block_number)?.into_view().header();
+    let mut header_deps = vec![payment_header.clone()];
+    let mut witness = None;
+    let seed = match (helper::payment_commitment(&wallet_cell[0].output_data), reveal_secret) {
+        (None, _) => payment_header.hash(),
+        (Some(commitment), Some((secret, timeout_secs))) => {
+            if helper::reveal_commitment(&secret) != commitment {
+                return Err(KabletopError::CommitmentMismatch);
+            }
+            let block_number = wallet_cell[0].block_number + helper::REVEAL_DELAY_BLOCKS;
+            let reveal_header = rpc::wait_block_header(block_number, timeout_secs).await?;
+            let seed = helper::reveal_seed(&reveal_header.hash(), &secret);
+            header_deps.push(reveal_header);
+            witness = Some(WitnessArgs::new_builder()
+                .input_type(Some(Bytes::from(secret.to_vec())).pack())
+                .build());
+            seed
+        },
+        (Some(_), None) => return Err(KabletopError::CommitmentMismatch)
+    };
+
     // prepare output data
-    let nft_config = helper::NFTConfig::from(config_cell[0].output_data.clone());
-    let block = rpc::get_block(wallet_cell[0].block_number)?.into_view();
+    let nft_config = helper::NFTConfig::try_from(config_cell[0].output_data.clone())?;
     let package_count = wallet_cell[0].output_data[0];
     let output_wallet_data = vec![0];
-    let output_nft_data = nft_config.rip_package(block.header().hash(), package_count);
+    let output_nft_data = nft_config.rip_package(seed, package_count);
 
     // prepare output cell
     let output_wallet = CellOutput::new_builder()
@@ -315,8 +488,12 @@ pub async fn build_tx_reveal_nft_package() -> Result<TransactionView> {
         .capacity(wallet_cell[0].output.capacity())
         .build();
 
+    let nft_lock = match nft_lock {
+        Some(nft_lock) => nft_lock,
+        None           => helper::sighash_script(&keystore::USER_PUBHASH[..])?
+    };
     let output_nft = CellOutput::new_builder()
-        .lock(helper::sighash_script(&keystore::USER_PUBHASH.to_vec()))
+        .lock(nft_lock)
         .type_(Some(nft_script).pack())
         .build_exact_capacity(Capacity::bytes(output_nft_data.len())?)?;
 
@@ -330,12 +507,252 @@ pub async fn build_tx_reveal_nft_package() -> Result<TransactionView> {
         .build();
 
     // complete tx
+    let payer_pkhash = fee_payer.map(helper::privkey_to_pkhash).unwrap_or(*keystore::USER_PUBHASH);
+    let tx = helper::complete_tx_with_sighash_cells(tx, &payer_pkhash, helper::fee("0.1")).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.payment).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.wallet).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.nft).await?;
+    let mut tx = helper::add_code_celldep(tx, config_cell[0].out_point.clone());
+    for header in header_deps {
+        tx = helper::add_headerdep(tx, header);
+    }
+
+    // place secret into the witness of wallet_cell which is the first input
+    if let Some(witness) = witness {
+        tx = tx
+            .as_advanced_builder()
+            .set_witnesses(vec![witness.as_bytes().pack()])
+            .build();
+    }
+
+    // sign tx, user signs wallet_cell and fee payer signs its own sighash cells
+    let tx = match fee_payer {
+        None        => signer::sign(tx, &keystore::USER_PRIVKEY, vec![], Box::new(|_| true)),
+        Some(payer) => {
+            let payer_lock = helper::sighash_script(&payer_pkhash[..])?;
+            let user_filter = {
+                let payer_lock = payer_lock.clone();
+                move |output: &CellOutput| output.lock() != payer_lock
+            };
+            let tx = signer::sign(tx, &keystore::USER_PRIVKEY, vec![], Box::new(user_filter));
+            signer::sign(tx, payer, vec![], Box::new(move |output| output.lock() == payer_lock))
+        }
+    };
+    Ok(tx)
+}
+
+/* CANCEL_NFT_PURCHASE
+*
+* to help other users cancel their purchased but unrevealed NFT packages, the wallet cell goes back to idle status and
+* the paid capacity returns to user
+*
+* a purchase can only be cancelled before its reveal result is known, otherwise users could rip packages offline and
+* cancel bad ones for free rerolls, so payment cell without commitment (seeded by the public payment block header) is
+* never cancellable, and payment cell with commitment is cancellable only if the cancel tx is committed before the block
+* REVEAL_DELAY_BLOCKS after payment block, which is checked against the current tip block
+*
+* NOTE: this only works with the nft_payment_contract which accepts store state changing from package_count back to 0
+* without any nft_cell output, and the contract itself must enforce the cancel window above (reject the refund once the
+* block REVEAL_DELAY_BLOCKS after payment block is reachable, and always for payment cell without commitment), the
+* checks here only keep honest users from building a rejected tx, so the payment contract entry of "Kabletop.toml" must
+* declare feature "purchase_refund" for such a contract, otherwise PurchaseNotCancellable returns before building
+*
+* // INPUT_CELL
+* on-chain wallet_cell (in reveal status)
+*
+* // OUTPUT_CELL_1 (same as wallet_cell)
+* celldeps：
+* 	  config_cell
+* data:
+* 	  0 (uint8)
+* lock:
+* 	  code_hash = nft_wallet_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = composer_pubkey_blake160
+* type:
+* 	  code_hash = nft_payment_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = user_pubkey_blake160 | series (uint8)
+* capacity:
+* 	  wallet_cell's capacity - package_price * package_count
+*
+* // OUTPUT_CELL_2
+* lock:
+* 	  code_hash = sighash_blake160
+* 	  hash_type = type
+* 	  args 	    = user_pubkey_blake160
+* capacity:
+* 	  package_price * package_count (fee subtracted)
+*/
+#[tracing::instrument]
+pub async fn build_tx_cancel_nft_purchase(series: u8) -> Result<TransactionView> {
+    if !_C.payment.supports(conf::PURCHASE_REFUND) {
+        return Err(KabletopError::PurchaseNotCancellable(String::from("payment contract doesn't support refunds")));
+    }
+
+    // prepare scripts
+    let wallet_script           = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
+    let composer_payment_script = helper::payment_script(helper::payment_args(&keystore::COMPOSER_PUBHASH, series)?);
+    let user_payment_script     = helper::payment_script(helper::payment_args(&keystore::USER_PUBHASH, series)?);
+
+    // check composer if has composed nft or not
+    let config_cell = rpc::get_exact_live_cells(wallet_script.clone(), composer_payment_script, 1).await?;
+    if config_cell.is_empty() {
+        return Err(KabletopError::ComposerNotFound);
+    }
+
+    // check user if has a pending package purchase
+    let wallet_cell = rpc::get_exact_live_cells(wallet_script.clone(), user_payment_script.clone(), 1).await?;
+    if wallet_cell.is_empty() {
+        return Err(KabletopError::StoreNotFound);
+    }
+    let package_count = match wallet_cell[0].output_data.first() {
+        Some(&count) if count > 0 => count,
+        _ => return Err(KabletopError::PaymentNotFound)
+    };
+
+    // refuse to cancel once the lottery seed could be known
+    if helper::payment_commitment(&wallet_cell[0].output_data).is_none() {
+        return Err(KabletopError::PurchaseNotCancellable(String::from("payment block header already seeds the reveal")));
+    }
+    let reveal_block_number = wallet_cell[0].block_number + helper::REVEAL_DELAY_BLOCKS;
+    if rpc::get_tip_block_number()? + 1 >= reveal_block_number {
+        return Err(KabletopError::PurchaseNotCancellable(format!("block #{} seeds the reveal soon", reveal_block_number)));
+    }
+
+    // prepare input cell
+    let input = CellInput::new_builder()
+        .previous_output(wallet_cell[0].out_point.clone())
+        .build();
+
+    // prepare output cell with paid capacity refunded
+    let nft_config = helper::NFTConfig::try_from(config_cell[0].output_data.clone())?;
+    let packages_price = nft_config.buy_package(package_count).as_u64();
+    let capacity: u64 = wallet_cell[0].output.capacity().unpack();
+    let output_data = [0u8];
+    let output = CellOutput::new_builder()
+        .lock(wallet_script)
+        .type_(Some(user_payment_script).pack())
+        .capacity(capacity.saturating_sub(packages_price).pack())
+        .build();
+    let occupied = output.occupied_capacity(Capacity::bytes(output_data.len())?)?.as_u64();
+    if capacity < packages_price + occupied {
+        return Err(KabletopError::InsufficientCapacity {
+            required:  packages_price + occupied,
+            available: capacity
+        });
+    }
+
+    // prepare tx
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .output(output)
+        .output_data(output_data.pack())
+        .build();
+
+    // complete tx, the paid capacity returns to user through sighash change cell
+    let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::USER_PUBHASH, helper::fee("0.1")).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.payment).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.wallet).await?;
+    let tx = helper::add_code_celldep(tx, config_cell[0].out_point.clone());
+
+    // sign tx
+    let tx = signer::sign(tx, &keystore::USER_PRIVKEY, vec![], Box::new(|_| true));
+    Ok(tx)
+}
+
+/* CLOSE_NFT_STORE
+*
+* to help other users close their idle NFT store and reclaim the capacity occupied by wallet cell, store with a
+* purchased but unrevealed package (store state isn't 0) can't be closed, and unwithdrawn revenue in wallet cell
+* goes back to composer, but if the revenue is too little to fill a cell the store can't be closed until composer
+* withdraws it by build_tx_withdraw_revenue, so that user never pays for the cell of composer
+*
+* NOTE: this only works with the nft_payment_contract which accepts wallet cell destroyed without any output under
+* the payment type script, so the payment contract entry of "Kabletop.toml" must declare feature "store_close" for
+* such a contract, otherwise FeatureMissing returns before building
+*
+* // INPUT_CELL
+* on-chain wallet_cell
+*
+* // OUTPUT_CELL_1 (only if wallet_cell has revenue)
+* lock:
+* 	  code_hash = sighash_blake160
+* 	  hash_type = type
+* 	  args 	    = composer_pubkey_blake160
+* capacity:
+* 	  revenue
+*
+* // OUTPUT_CELL_2
+* lock:
+* 	  code_hash = sighash_blake160
+* 	  hash_type = type
+* 	  args 	    = user_pubkey_blake160
+* capacity:
+* 	  occupied capacity of wallet_cell (fee subtracted)
+*/
+#[tracing::instrument]
+pub async fn build_tx_close_nft_store(series: u8) -> Result<TransactionView> {
+    _C.payment.require("payment", conf::STORE_CLOSE)?;
+
+    // prepare scripts
+    let wallet_script           = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
+    let composer_payment_script = helper::payment_script(helper::payment_args(&keystore::COMPOSER_PUBHASH, series)?);
+    let user_payment_script     = helper::payment_script(helper::payment_args(&keystore::USER_PUBHASH, series)?);
+
+    // check composer if has composed nft or not
+    let config_cell = rpc::get_exact_live_cells(wallet_script.clone(), composer_payment_script, 1).await?;
+    if config_cell.is_empty() {
+        return Err(KabletopError::ComposerNotFound);
+    }
+
+    // check user if has created a nft store and on the idle status
+    let wallet_cell = rpc::get_exact_live_cells(wallet_script.clone(), user_payment_script, 1).await?;
+    if wallet_cell.is_empty() {
+        return Err(KabletopError::StoreNotFound);
+    }
+	if wallet_cell[0].output_data.first() != Some(&0) {
+        return Err(KabletopError::StorePendingReveal);
+	}
+
+    // prepare input cell
+    let input = CellInput::new_builder()
+        .previous_output(wallet_cell[0].out_point.clone())
+        .build();
+
+    // prepare composer output cell to receive revenue
+    let capacity: u64 = wallet_cell[0].output.capacity().unpack();
+    let occupied = wallet_cell[0].output
+        .occupied_capacity(Capacity::bytes(wallet_cell[0].output_data.len())?)?
+        .as_u64();
+    let mut outputs = vec![];
+    if capacity > occupied {
+        let output = CellOutput::new_builder()
+            .lock(helper::sighash_script(&keystore::COMPOSER_PUBHASH[..])?)
+            .build_exact_capacity(Capacity::zero())?;
+        let minimal: u64 = output.capacity().unpack();
+        if capacity - occupied < minimal {
+            return Err(KabletopError::UnwithdrawnRevenue(capacity - occupied));
+        }
+        let output = output
+            .as_builder()
+            .capacity((capacity - occupied).pack())
+            .build();
+        outputs.push(output);
+    }
+
+    // prepare tx
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .outputs_data(vec![Bytes::new(); outputs.len()].pack())
+        .outputs(outputs)
+        .build();
+
+    // complete tx, the occupied capacity of wallet_cell returns to user through sighash change cell
     let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::USER_PUBHASH, helper::fee("0.1")).await?;
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.payment.tx_hash.clone(), 0));
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.wallet.tx_hash.clone(), 0));
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.nft.tx_hash.clone(), 0));
+    let tx = helper::add_contract_celldep(tx, &_C.payment).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.wallet).await?;
     let tx = helper::add_code_celldep(tx, config_cell[0].out_point.clone());
-    let tx = helper::add_headerdep(tx, block.header());
 
     // sign tx
     let tx = signer::sign(tx, &keystore::USER_PRIVKEY, vec![], Box::new(|_| true));
@@ -344,23 +761,31 @@ pub async fn build_tx_reveal_nft_package() -> Result<TransactionView> {
 
 /* DISCARD_NFT_CELL
 * 
-* to help discard helpless nfts to save CKB locked by NFT cell
+* to help discard helpless nfts to save CKB locked by NFT cell, nft cells are collected under user sighash lock and
+* [owner_locks], and only the sighash group is signed here, the groups under [owner_locks] are left for caller to sign
 */
-pub async fn build_tx_discard_nft(discard_nfts: Vec<[u8; 20]>) -> Result<TransactionView> {
+#[tracing::instrument(skip_all, fields(nfts = discard_nfts.len()))]
+pub async fn build_tx_discard_nft(discard_nfts: Vec<[u8; 20]>, owner_locks: Vec<Script>) -> Result<TransactionView> {
+    let owner_locks = helper::owner_locks(&keystore::USER_PUBHASH[..], &owner_locks)?;
     let tx = TransactionBuilder::default().build();
-	let tx = helper::complete_tx_with_nft_cells(tx, &keystore::USER_PUBHASH, &keystore::COMPOSER_PUBHASH, discard_nfts, true).await?;
+	let tx = helper::complete_tx_with_owned_nft_cells(tx, &owner_locks, &keystore::COMPOSER_PUBHASH, discard_nfts, true).await?;
 	let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::USER_PUBHASH, helper::fee("0.1")).await?;
-	let tx = signer::sign(tx, &keystore::USER_PRIVKEY, vec![], Box::new(|_| true));		
+	let tx = signer::sign_sighash(tx, &keystore::USER_PRIVKEY, vec![])?;
 	Ok(tx)
 }
 
 /* TRANSFER_NFT_CELL
 * 
-* to help transfer owned nfts to recevier address
+* to help transfer owned nfts to recevier lock, which could be any lock (e.g. sighash, multisig, anyone-can-pay or a
+* time-lock escrow), nft cells are collected under user sighash lock and [owner_locks], and only the sighash group is
+* signed here, the groups under [owner_locks] are left for caller to sign
 */
-pub async fn build_tx_transfer_nft(transfer_nfts: Vec<[u8; 20]>, receiver_pkhash: [u8; 20]) -> Result<TransactionView> {
+#[tracing::instrument(skip_all, fields(nfts = transfer_nfts.len(), receiver = %receiver_lock.calc_script_hash()))]
+pub async fn build_tx_transfer_nft(
+    transfer_nfts: Vec<[u8; 20]>, receiver_lock: Script, owner_locks: Vec<Script>
+) -> Result<TransactionView> {
 	// prepare recevier nft cell
-    let lock_script = helper::sighash_script(&receiver_pkhash[..]);
+    let lock_script = receiver_lock;
     let type_script = {
         let wallet = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
         helper::nft_script(wallet.calc_script_hash().raw_data().to_vec())
@@ -380,63 +805,564 @@ pub async fn build_tx_transfer_nft(transfer_nfts: Vec<[u8; 20]>, receiver_pkhash
 		.output(receiver_output)
 		.output_data(Bytes::from(output_data).pack())
 		.build();
-	let tx = helper::complete_tx_with_nft_cells(tx, &keystore::USER_PUBHASH, &keystore::COMPOSER_PUBHASH, transfer_nfts, true).await?;
+    let owner_locks = helper::owner_locks(&keystore::USER_PUBHASH[..], &owner_locks)?;
+	let tx = helper::complete_tx_with_owned_nft_cells(tx, &owner_locks, &keystore::COMPOSER_PUBHASH, transfer_nfts, true).await?;
 	let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::USER_PUBHASH, helper::fee("0.1")).await?;
-	let tx = signer::sign(tx, &keystore::USER_PRIVKEY, vec![], Box::new(|_| true));
+	let tx = signer::sign_sighash(tx, &keystore::USER_PRIVKEY, vec![])?;
 	Ok(tx)
 }
 
+/* TRANSFER_NFT_CELLS_BATCH
+*
+* to help transfer owned nfts to many receivers in one tx (e.g. tournament prizes or airdrops), each receiver gets one
+* nft cell, and the other nfts in consumed nft cells are kept in one change nft cell for each lock of them, transfers
+* are taken in order until the estimated tx size reaches MAX_TX_SIZE, and the transfers left out are returned for the
+* next tx, it's an error if the first transfer alone can't fit in one tx, nft cells are collected under user sighash
+* lock and [owner_locks], and only the sighash group is signed here, the groups under [owner_locks] are left for caller
+* to sign
+*
+* // OUTPUT_CELLS (one for each receiver)
+* data:
+* 	  blake160 | blake160 | ...
+* lock:
+* 	  receiver lock (any)
+* type:
+* 	  code_hash = nft_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = nft_wallet_lockhash
+*
+* // OUTPUT_CELLS (change, one for each lock of consumed nft_cells)
+* data:
+* 	  blake160 | blake160 | ... (nfts left in consumed nft_cells under the lock)
+* lock:
+* 	  the lock of consumed nft_cells
+* type:
+* 	  (same as above)
+*/
+#[tracing::instrument(skip_all, fields(receivers = transfers.len()))]
+pub async fn build_tx_transfer_nft_batch(
+    transfers: Vec<(Script, Vec<[u8; 20]>)>, owner_locks: Vec<Script>
+) -> Result<(TransactionView, Vec<(Script, Vec<[u8; 20]>)>)> {
+    // prepare scripts
+    let type_script = {
+        let wallet = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
+        helper::nft_script(wallet.calc_script_hash().raw_data().to_vec())
+    };
+    if transfers.is_empty() || transfers.iter().any(|(_, nfts)| nfts.is_empty()) {
+        return Err(KabletopError::InvalidTransaction(String::from("empty nft transfer")));
+    }
+
+    // prepare receiver outputs until the estimated tx size is reached, input nft cells are picked in insertion order
+    // only when the spare nfts in the picked ones can't cover the transfer, and the change cells are counted in reserved
+    let owner_locks = helper::owner_locks(&keystore::USER_PUBHASH[..], &owner_locks)?;
+    let inventory = rpc::get_nft_inventory_by_locks(owner_locks, Some(type_script.clone()), 10).await?;
+    let cells = inventory.cells();
+    let mut holders: HashMap<[u8; 20], VecDeque<usize>> = HashMap::new();
+    for (i, cell) in cells.iter().enumerate() {
+        for (_, nft) in &cell.nfts {
+            holders.entry(*nft).or_default().push_back(i);
+        }
+    }
+    let mut picked = vec![false; cells.len()];
+    let mut picked_cells = vec![];
+    let mut spare_nfts = helper::NftBag::new();
+    let mut outputs = vec![];
+    let mut outputs_data = vec![];
+    let mut outputs_size = 0usize;
+    let mut inputs_size = 0usize;
+    let mut required_nfts = vec![];
+    let mut left_transfers = vec![];
+    for (receiver_lock, nfts) in transfers {
+        if !left_transfers.is_empty() {
+            left_transfers.push((receiver_lock, nfts));
+            continue
+        }
+        let output_data = nfts.concat();
+        let output = CellOutput::new_builder()
+            .lock(receiver_lock.clone())
+            .type_(Some(type_script.clone()).pack())
+            .build_exact_capacity(Capacity::bytes(output_data.len())?)?;
+        let output_size = output.as_slice().len() + output_data.len() + 8;
+
+        // the uncovered nfts are left to be reported by completing tx
+        let picked_count = picked_cells.len();
+        let mut input_size = 0usize;
+        for nft in &nfts {
+            if spare_nfts.remove(nft) {
+                continue
+            }
+            let holder = holders
+                .get_mut(nft)
+                .and_then(|holder| std::iter::from_fn(|| holder.pop_front()).find(|&i| !picked[i]));
+            if let Some(i) = holder {
+                picked[i] = true;
+                picked_cells.push(i);
+                cells[i].nfts.iter().for_each(|(_, nft)| spare_nfts.insert(*nft));
+                spare_nfts.remove(nft);
+                input_size += CellInput::TOTAL_SIZE + cells[i].nfts.len() * 20;
+            }
+        }
+        let size = outputs_size + output_size + inputs_size + input_size;
+        if size > MAX_TX_SIZE - TX_SIZE_RESERVED {
+            if outputs.is_empty() {
+                return Err(KabletopError::InvalidTransaction(format!("the first nft transfer alone takes {} bytes", size)));
+            }
+            picked_cells.truncate(picked_count);
+            left_transfers.push((receiver_lock, nfts));
+            continue
+        }
+        outputs_size += output_size;
+        inputs_size += input_size;
+        required_nfts.extend_from_slice(&nfts);
+        outputs.push(output);
+        outputs_data.push(Bytes::from(output_data).pack());
+    }
+    let mut picked_inventory = helper::NftInventory::new();
+    for i in picked_cells {
+        for (nft, location) in cells[i].locations() {
+            picked_inventory.insert(nft, location);
+        }
+    }
+
+    // complete transfer tx
+    let tx = TransactionBuilder::default()
+        .outputs(outputs)
+        .outputs_data(outputs_data)
+        .build();
+    let tx = helper::complete_tx_with_nft_inventory(tx, &picked_inventory, required_nfts, true).await?;
+    let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::USER_PUBHASH, helper::fee("0.1")).await?;
+    let tx = signer::sign_sighash(tx, &keystore::USER_PRIVKEY, vec![])?;
+    Ok((tx, left_transfers))
+}
+
+// transfer nfts to many receivers by [transfers], which are split into several txs if they can't fit in one, each tx
+// is sent after the previous one is committed and synced by ckb-indexer in [timeout_secs], so that the next tx won't
+// pick the spent nft cells, returns hashes of all sent txs
+//
+// txs are sent right after built, so nft cells are only collected under user sighash lock here
+#[tracing::instrument(skip_all, fields(receivers = transfers.len()))]
+pub async fn transfer_nft_batch(mut transfers: Vec<(Script, Vec<[u8; 20]>)>, timeout_secs: u64) -> Result<Vec<H256>> {
+    let mut tx_hashes = vec![];
+    while !transfers.is_empty() {
+        let (tx, left_transfers) = build_tx_transfer_nft_batch(transfers, vec![]).await?;
+        let tx_hash = rpc::send_transaction(tx.data())?;
+        rpc::wait_transaction_committed(tx_hash.clone(), timeout_secs).await?;
+        rpc::wait_indexer_synced(rpc::get_tip_block_number()?, timeout_secs).await?;
+        tx_hashes.push(tx_hash);
+        transfers = left_transfers;
+    }
+    Ok(tx_hashes)
+}
+
+/* MERGE_NFT_CELLS
+*
+* to help fold many small nft cells owned by [owner] into one nft cell, which saves the capacity occupied by the cell
+* overhead of each nft cell, at most [max_inputs] nft cells are merged in one tx and the tx size is limited under
+* MAX_TX_SIZE, so to merge more cells just build it again after the previous one is committed, nft cells are collected
+* under owner sighash lock and [owner_locks], and only cells under the same lock are merged so that no nft changes its
+* lock, only the sighash group is signed here, the groups under [owner_locks] are left for caller to sign
+*
+* // INPUT_CELLS
+* on-chain nft_cells of owner
+*
+* // OUTPUT_CELLS (one for each lock of input nft_cells)
+* data:
+* 	  blake160 | blake160 | ... (all nfts from input nft_cells under the lock)
+* lock:
+* 	  the lock of input nft_cells
+* type:
+* 	  code_hash = nft_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = nft_wallet_lockhash
+*/
+#[tracing::instrument(skip(owner, owner_locks))]
+pub async fn build_tx_merge_nft_cells(owner: &Privkey, max_inputs: usize, owner_locks: Vec<Script>) -> Result<TransactionView> {
+    // prepare scripts
+    let owner_pkhash = helper::privkey_to_pkhash(owner);
+    let type_script  = {
+        let wallet = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
+        helper::nft_script(wallet.calc_script_hash().raw_data().to_vec())
+    };
+
+    // pick nft cells until max_inputs or estimated tx size is reached, and group them by lock
+    let owner_locks = helper::owner_locks(&owner_pkhash[..], &owner_locks)?;
+    let inventory = rpc::get_nft_inventory_by_locks(owner_locks, Some(type_script.clone()), 10).await?;
+    let mut size = 0usize;
+    let mut groups: Vec<(Script, Vec<CellInput>, u64, Vec<u8>)> = vec![];
+    for (picked_count, cell) in inventory.cells().into_iter().enumerate() {
+        let cell_size = CellInput::TOTAL_SIZE + cell.nfts.len() * 20;
+        if picked_count >= max_inputs || size + cell_size > MAX_TX_SIZE - TX_SIZE_RESERVED {
+            break
+        }
+        size += cell_size;
+        let index = match groups.iter().position(|(lock_script, _, _, _)| lock_script == &cell.lock_script) {
+            Some(index) => index,
+            None => {
+                groups.push((cell.lock_script.clone(), vec![], 0, vec![]));
+                groups.len() - 1
+            }
+        };
+        let (_, inputs, capacity, output_data) = &mut groups[index];
+        inputs.push(CellInput::new_builder()
+            .previous_output(cell.out_point)
+            .build());
+        *capacity += cell.capacity;
+        cell.nfts.iter().for_each(|(_, nft)| output_data.extend_from_slice(nft));
+    }
+
+    // a lone nft cell under its lock has nothing to merge with, so it's left untouched
+    groups.retain(|(_, inputs, _, _)| inputs.len() > 1);
+    if groups.is_empty() {
+        return Err(KabletopError::InvalidTransaction(String::from("no more than one nft cell to merge under any lock")));
+    }
+
+    // prepare tx with one output cell for each lock, the capacity released from cells under owner sighash lock returns
+    // through sighash change cell, and the cells under other locks keep all their capacity
+    let sighash_lock = helper::sighash_script(&owner_pkhash[..])?;
+    let mut tx = TransactionBuilder::default();
+    for (lock_script, inputs, capacity, output_data) in groups {
+        let mut output = CellOutput::new_builder()
+            .lock(lock_script.clone())
+            .type_(Some(type_script.clone()).pack())
+            .build_exact_capacity(Capacity::bytes(output_data.len())?)?;
+        if lock_script != sighash_lock {
+            output = output
+                .as_builder()
+                .capacity(capacity.pack())
+                .build();
+        }
+        tx = tx
+            .inputs(inputs)
+            .output(output)
+            .output_data(Bytes::from(output_data).pack());
+    }
+    let tx = tx.build();
+    let tx = helper::complete_tx_with_sighash_cells(tx, &owner_pkhash, helper::fee("0.1")).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.nft).await?;
+
+    // sign tx
+    let tx = signer::sign_sighash(tx, owner, vec![])?;
+    Ok(tx)
+}
+
+/* SPLIT_NFT_CELLS
+*
+* to help lay out nfts owned by [owner] into chosen cells, one nft cell under [group_lock] for each group of [groups]
+* (e.g. one cell per deck), the other nfts in consumed nft cells are put into one more nft cell for each lock of them,
+* nft cells are collected under owner sighash lock and [owner_locks], and only the sighash group is signed here, the
+* groups under [owner_locks] are left for caller to sign
+*
+* // INPUT_CELLS
+* on-chain nft_cells of owner which cover all nfts in groups
+*
+* // OUTPUT_CELLS (one for each group)
+* data:
+* 	  blake160 | blake160 | ...
+* lock:
+* 	  group_lock (any), e.g. sighash_blake160 with owner_pubkey_blake160
+* type:
+* 	  code_hash = nft_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = nft_wallet_lockhash
+*
+* // OUTPUT_CELLS (change, one for each lock of consumed nft_cells)
+* data:
+* 	  blake160 | blake160 | ... (nfts left in consumed nft_cells under the lock)
+* lock:
+* 	  the lock of consumed nft_cells
+* type:
+* 	  (same as above)
+*/
+#[tracing::instrument(skip_all, fields(groups = groups.len(), group_lock = %group_lock.calc_script_hash()))]
+pub async fn build_tx_split_nft_cells(
+    owner: &Privkey, groups: Vec<Vec<[u8; 20]>>, group_lock: Script, owner_locks: Vec<Script>
+) -> Result<TransactionView> {
+    // prepare scripts
+    let owner_pkhash = helper::privkey_to_pkhash(owner);
+    let type_script  = {
+        let wallet = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
+        helper::nft_script(wallet.calc_script_hash().raw_data().to_vec())
+    };
+    if groups.is_empty() || groups.iter().any(|nfts| nfts.is_empty()) {
+        return Err(KabletopError::InvalidTransaction(String::from("empty nft group to split")));
+    }
+
+    // prepare output cells
+    let outputs_data = groups
+        .iter()
+        .map(|nfts| nfts.concat())
+        .collect::<Vec<_>>();
+    let mut outputs = vec![];
+    for data in &outputs_data {
+        let output = CellOutput::new_builder()
+            .lock(group_lock.clone())
+            .type_(Some(type_script.clone()).pack())
+            .build_exact_capacity(Capacity::bytes(data.len())?)?;
+        outputs.push(output);
+    }
+
+    // prepare tx with nft cells which cover all nfts in groups
+    let tx = TransactionBuilder::default()
+        .outputs(outputs)
+        .outputs_data(outputs_data.into_iter().map(|data| Bytes::from(data).pack()))
+        .build();
+    let owner_locks = helper::owner_locks(&owner_pkhash[..], &owner_locks)?;
+    let inventory = rpc::get_nft_inventory_covering(owner_locks, Some(type_script), &groups.concat(), 10).await?;
+    let tx = helper::complete_tx_with_nft_inventory(tx, &inventory, groups.concat(), true).await?;
+    let tx = helper::complete_tx_with_sighash_cells(tx, &owner_pkhash, helper::fee("0.1")).await?;
+
+    // sign tx
+    let tx = signer::sign_sighash(tx, owner, vec![])?;
+    Ok(tx)
+}
+
 /* ISSUE_NFT_CELL
 * 
-* to additionally issue nfts to receiver address for TEST
+* to additionally issue nfts to receiver lock for TEST, which is only compiled with feature "test-utils" and refuses
+* to run on mainnet
+*/
+#[cfg(any(test, feature = "test-utils"))]
+#[tracing::instrument(skip_all, fields(nfts = issue_nfts.len(), receiver = %receiver_lock.calc_script_hash()))]
+pub async fn build_tx_issue_nft(series: u8, issue_nfts: Vec<[u8; 20]>, receiver_lock: Script) -> Result<TransactionView> {
+    helper::forbid_mainnet("build_tx_issue_nft")?;
+    issue_nft(series, vec![(receiver_lock, issue_nfts)], vec![]).await
+}
+
+/* AIRDROP_NFT_CELLS
+*
+* to help nft composers issue promotional nfts in [series] to many receivers without payment, each receiver gets one
+* nft cell, and the airdrop is recorded on chain as an extra witness signed by composer (see [helper::AirdropRecord])
+*
+* // OUTPUT_CELLS (one for each receiver)
+* data:
+* 	  blake160 | blake160 | ...
+* lock:
+* 	  receiver lock (any)
+* type:
+* 	  code_hash = nft_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = nft_wallet_lockhash
+*
+* // EXTRA_WITNESS
+* output_type:
+*     "airdrop" | series(u8) | nft_count(u32) | memo(utf8)
 */
-pub async fn build_tx_issue_nft(issue_nfts: Vec<[u8; 20]>, receiver_pkhash: [u8; 20]) -> Result<TransactionView> {
+#[tracing::instrument(skip(airdrops), fields(receivers = airdrops.len()))]
+pub async fn build_tx_airdrop_nft(series: u8, airdrops: Vec<(Script, Vec<[u8; 20]>)>, memo: String) -> Result<TransactionView> {
+    if airdrops.is_empty() || airdrops.iter().any(|(_, nfts)| nfts.is_empty()) {
+        return Err(KabletopError::InvalidTransaction(String::from("empty nft airdrop")));
+    }
+    let record = helper::AirdropRecord {
+        series,
+        nft_count: airdrops.iter().map(|(_, nfts)| nfts.len() as u32).sum(),
+        memo
+    };
+    issue_nft(series, airdrops, vec![record.to_witness()]).await
+}
+
+// issue nfts in [series] to each receiver lock of [issues] by consuming and recreating the composer config cell
+async fn issue_nft(
+    series: u8, issues: Vec<(Script, Vec<[u8; 20]>)>, extra_witnesses: Vec<WitnessArgs>
+) -> Result<TransactionView> {
     // prepare scripts
     let wallet_script = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
-    let payment_script = helper::payment_script(keystore::COMPOSER_PUBHASH.to_vec());
+    let payment_script = helper::payment_script(helper::payment_args(&keystore::COMPOSER_PUBHASH, series)?);
 
     // prepare input cell
-    let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock).filter(payment_script.clone().into());
-    let composer_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
+    let composer_cell = rpc::get_exact_live_cells(wallet_script.clone(), payment_script.clone(), 1).await?;
     if composer_cell.is_empty() {
-        return Err(anyhow!("composer hasn't composed any NFTs yet."));
+        return Err(KabletopError::ComposerNotFound);
     }
     let composer_input = CellInput::new_builder()
         .previous_output(composer_cell[0].out_point.clone())
         .build();
 
-	// prepare recevier nft cell
-    let lock_script = helper::sighash_script(&receiver_pkhash[..]);
-    let type_script = {
-        let wallet = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
-        helper::nft_script(wallet.calc_script_hash().raw_data().to_vec())
-    };
-	let output_data = issue_nfts
-		.iter()
-		.map(|nft| nft.to_vec())
-		.collect::<Vec<Vec<u8>>>()
-		.concat();
-    let receiver_output = CellOutput::new_builder()
-        .lock(lock_script)
-        .type_(Some(type_script).pack())
-		.build_exact_capacity(Capacity::bytes(output_data.len())?)?;
+    // only nfts composed in the config of [series] can be issued
+    let series_nfts = helper::NFTConfig::try_from(composer_cell[0].output_data.clone())?.nfts();
+    if let Some(nft) = issues.iter().flat_map(|(_, nfts)| nfts).find(|nft| !series_nfts.contains(nft)) {
+        return Err(KabletopError::InvalidTransaction(format!("nft {} isn't composed in series {}", hex::encode(nft), series)));
+    }
+
+	// prepare recevier nft cells
+    let type_script = helper::nft_script(wallet_script.calc_script_hash().raw_data().to_vec());
+    let mut outputs = vec![];
+    let mut outputs_data = vec![];
+    for (receiver_lock, nfts) in issues {
+        let output_data = nfts.concat();
+        let receiver_output = CellOutput::new_builder()
+            .lock(receiver_lock)
+            .type_(Some(type_script.clone()).pack())
+            .build_exact_capacity(Capacity::bytes(output_data.len())?)?;
+        outputs.push(receiver_output);
+        outputs_data.push(Bytes::from(output_data).pack());
+    }
 		
 	// complete tx
 	let tx = TransactionBuilder::default()
 		.input(composer_input)
-		.output(receiver_output)
+		.outputs(outputs)
 		.output(composer_cell[0].output.clone())
-		.output_data(Bytes::from(output_data).pack())
+		.outputs_data(outputs_data)
 		.output_data(composer_cell[0].output_data.pack())
 		.build();
 	let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::COMPOSER_PUBHASH, helper::fee("0.1")).await?;
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.payment.tx_hash.clone(), 0));
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.wallet.tx_hash.clone(), 0));
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.nft.tx_hash.clone(), 0));
-	let tx = signer::sign(tx, &keystore::COMPOSER_PRIVKEY, vec![], Box::new(|_| true));
+    let tx = helper::add_contract_celldep(tx, &_C.payment).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.wallet).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.nft).await?;
+	let tx = signer::sign(tx, &keystore::COMPOSER_PRIVKEY, extra_witnesses, Box::new(|_| true));
 	Ok(tx)
 }
 
+/* WITHDRAW_REVENUE
+*
+* to help composer collect purchase payments which are accumulated in wallet cells of user stores, every idle store
+* keeps its occupied capacity and the rest goes to the composer-chosen [receiver_lock], stores that are pending reveal
+* (store state isn't 0) are skipped because their capacity is still owed to the packages which haven't been ripped
+*
+* celldeps：
+* 	  config_cell (one for each series of consumed wallet_cells)
+*
+* // INPUT_CELLS
+* on-chain wallet_cells from [stores] (all idle stores if [stores] is empty)
+*
+* // OUTPUT_CELL_1..N (same as wallet_cell)
+* data:
+* 	  0 (uint8)
+* lock:
+* 	  code_hash = nft_wallet_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = composer_pubkey_blake160
+* type:
+* 	  code_hash = nft_payment_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = user_pubkey_blake160 | series (uint8)
+* capacity:
+* 	  occupied capacity of wallet_cell
+*
+* // OUTPUT_CELL_N+1
+* lock:
+* 	  receiver_lock
+* capacity:
+* 	  total revenue (fee subtracted)
+*/
+#[tracing::instrument(skip_all, fields(stores = stores.len(), receiver = %receiver_lock.calc_script_hash()))]
+pub async fn build_tx_withdraw_revenue(stores: Vec<[u8; 20]>, receiver_lock: Script) -> Result<TransactionView> {
+    // prepare scripts
+    let wallet_script  = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
+    let payment_script = helper::payment_script(keystore::COMPOSER_PUBHASH.to_vec());
+
+    // collect idle wallet cells of all series which hold revenue
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    let mut outputs_data = vec![];
+    let mut revenue = 0u64;
+    let mut series = BTreeSet::new();
+    let mut cursor = None;
+    loop {
+        let search_key = SearchKey::new(wallet_script.clone().into(), ScriptType::Lock);
+        let live_cells = rpc::get_live_cells(search_key, 20, cursor).await?;
+        for cell in &live_cells.objects {
+            let store = store_revenue(&cell.output, &cell.output_data, &payment_script, &keystore::COMPOSER_PUBHASH, &stores)?;
+            if let Some((store_series, store_revenue, output)) = store {
+                revenue += store_revenue;
+                series.insert(store_series);
+                inputs.push(
+                    CellInput::new_builder()
+                        .previous_output(cell.out_point.clone())
+                        .build()
+                );
+                outputs.push(output);
+                outputs_data.push(cell.output_data.clone());
+            }
+        }
+        if live_cells.last_cursor.is_empty() {
+            break
+        }
+        cursor = Some(live_cells.last_cursor);
+    }
+    if inputs.is_empty() {
+        return Err(KabletopError::StoreNotFound);
+    }
+
+    // prepare receiver output cell, the fee is paid from revenue
+    let fee = helper::fee("0.1");
+    let receiver_output = CellOutput::new_builder()
+        .lock(receiver_lock)
+        .build_exact_capacity(Capacity::zero())?;
+    let required: u64 = receiver_output.capacity().unpack();
+    if revenue < required + fee.as_u64() {
+        return Err(KabletopError::InsufficientCapacity {
+            required:  required + fee.as_u64(),
+            available: revenue
+        });
+    }
+    let receiver_output = receiver_output
+        .as_builder()
+        .capacity((revenue - fee.as_u64()).pack())
+        .build();
+    outputs.push(receiver_output);
+    outputs_data.push(Bytes::new());
+
+    // prepare tx
+    let tx = TransactionBuilder::default()
+        .inputs(inputs)
+        .outputs(outputs)
+        .outputs_data(outputs_data.pack())
+        .build();
+
+    // complete tx, no more sighash cells will be collected since revenue has already covered the fee
+    let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::COMPOSER_PUBHASH, fee).await?;
+    let tx = helper::add_contract_celldep(tx, &_C.payment).await?;
+    let mut tx = helper::add_contract_celldep(tx, &_C.wallet).await?;
+
+    // the payment contract checks wallet cells against the config cell of their series as other builders do
+    for series in series {
+        let composer_payment_script = helper::payment_script(helper::payment_args(&keystore::COMPOSER_PUBHASH, series)?);
+        let config_cell = rpc::get_exact_live_cells(wallet_script.clone(), composer_payment_script, 1).await?;
+        if config_cell.is_empty() {
+            return Err(KabletopError::ComposerNotFound);
+        }
+        tx = helper::add_code_celldep(tx, config_cell[0].out_point.clone());
+    }
+
+    // sign tx
+    let tx = signer::sign(tx, &keystore::COMPOSER_PRIVKEY, vec![], Box::new(|_| true));
+    Ok(tx)
+}
+
+// get (series, revenue, wallet output) of wallet cell [output] with [data] if it's an idle store of user in [stores]
+// (any user but composer [composer_pkhash] if empty) under the same payment contract as [payment_script], the wallet
+// output keeps only the occupied capacity, and stores pending reveal or without revenue are None
+fn store_revenue(
+    output: &CellOutput, data: &[u8], payment_script: &Script, composer_pkhash: &[u8; 20], stores: &[[u8; 20]]
+) -> Result<Option<(u8, u64, CellOutput)>> {
+    let args = match output.type_().to_opt() {
+        Some(script) if script.code_hash() == payment_script.code_hash()
+            && script.hash_type() == payment_script.hash_type()
+            && script.args().raw_data().len() >= 20
+            && script.args().raw_data()[..20] != composer_pkhash[..] => script.args().raw_data(),
+        _ => return Ok(None)
+    };
+    if !stores.is_empty() && !stores.iter().any(|pkhash| pkhash[..] == args[..20]) {
+        return Ok(None)
+    }
+    if data.first() != Some(&0) {
+        tracing::debug!(store = %hex::encode(&args[..20]), "skip store pending reveal");
+        return Ok(None)
+    }
+    let capacity: u64 = output.capacity().unpack();
+    let occupied = output.occupied_capacity(Capacity::bytes(data.len())?)?.as_u64();
+    if capacity <= occupied {
+        return Ok(None)
+    }
+    let output = output
+        .clone()
+        .as_builder()
+        .capacity(occupied.pack())
+        .build();
+    Ok(Some((helper::payment_series(&args), capacity - occupied, output)))
+}
+
 /* CHALLENGE_CELL
 *
 * to help user create a channel challenge tx which will consume previous channel cell no matter it's in original
@@ -463,6 +1389,7 @@ pub async fn build_tx_issue_nft(issue_nfts: Vec<[u8; 20]>, receiver_pkhash: [u8;
 * 	  ...
 * ]
 */
+#[tracing::instrument(skip(channel_args, pending_operations, rounds), fields(rounds = rounds.len()))]
 pub async fn build_tx_challenge_channel(
     channel_args: Vec<u8>, challenger: u8, pending_operations: protocol::Operations, rounds: Vec<(protocol::Round, Signature)>
 ) -> Result<TransactionView> {
@@ -472,7 +1399,7 @@ pub async fn build_tx_challenge_channel(
     let search_key = SearchKey::new(channel_script.clone().into(), ScriptType::Lock);
     let channel_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
     if channel_cell.is_empty() {
-        return Err(anyhow!("channel with specified channel_script is non-existent"));
+        return Err(KabletopError::ChannelNotFound);
     }
 
 	let mut outputs = vec![];
@@ -484,7 +1411,7 @@ pub async fn build_tx_challenge_channel(
 	if channel_cell[0].output_data.len() > 0 {
 		let challenge = protocol::Challenge::from_slice(channel_cell[0].output_data.to_vec().as_slice())?;
 		if u8::from(challenge.challenger()) == challenger {
-			return Err(anyhow!("one challenger can't challenge twice"));
+			return Err(KabletopError::DuplicateChallenge);
 		}
 		let pkhash = {
 			if challenger == 1 {
@@ -567,7 +1494,7 @@ pub async fn build_tx_challenge_channel(
         .outputs_data(outputs_data.pack())
         .build();
     tx = helper::complete_tx_with_sighash_cells(tx, &keystore::USER_PUBHASH, helper::fee("0.1")).await?;
-    tx = helper::add_code_celldep(tx, OutPoint::new(_C.kabletop.tx_hash.clone(), 0));
+    tx = helper::add_contract_celldep(tx, &_C.kabletop).await?;
 	for luacode in &_C.luacodes {
 		tx = helper::add_code_celldep(tx, OutPoint::new(luacode.tx_hash.clone(), 0));
 	}
@@ -619,11 +1546,12 @@ pub async fn build_tx_challenge_channel(
 * 	...
 * ]
 */
+#[tracing::instrument(skip(channel_args, rounds), fields(rounds = rounds.len()))]
 pub async fn build_tx_close_channel(
     channel_args: Vec<u8>, rounds: Vec<(protocol::Round, Signature)>, winner: u8, from_challenge: bool
 ) -> Result<TransactionView> {
 	if rounds.is_empty() {
-		return Err(anyhow!("kabletop rounds is empty"));
+		return Err(KabletopError::EmptyRounds);
 	}
     // make sure channel stays open
 	let kabletop_args = protocol::Args::from_slice(channel_args.as_slice())?;
@@ -632,7 +1560,7 @@ pub async fn build_tx_close_channel(
     let search_key = SearchKey::new(kabletop_script.into(), ScriptType::Lock);
     let channel_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
     if channel_cell.is_empty() {
-        return Err(anyhow!("channel with specified channel_script is non-existent"));
+        return Err(KabletopError::ChannelNotFound);
     }
 
     // prepare input and witnesses
@@ -663,7 +1591,7 @@ pub async fn build_tx_close_channel(
 	};
     let staking_ckb: u64 = kabletop_args.user_staking_ckb().into();
     if channel_ckb <= staking_ckb * 2 {
-        return Err(anyhow!("broken channel with wrong cell capacity"));
+        return Err(KabletopError::BrokenChannel("wrong cell capacity"));
     }
     let bet_ckb = channel_ckb / 2 - staking_ckb;
     let mut user1_capacity = staking_ckb;
@@ -671,18 +1599,17 @@ pub async fn build_tx_close_channel(
     match winner {
         1 => user1_capacity += 2 * bet_ckb,
         2 => user2_capacity += 2 * bet_ckb,
-        _ => return Err(anyhow!("winner must be 1 or 2"))
+        _ => return Err(KabletopError::InvalidWinner(winner))
     }
 	if channel_cell[0].output_data.len() > 0 {
 		let challenge = protocol::Challenge::from_slice(channel_cell[0].output_data.to_vec().as_slice())?;
 		match u8::from(challenge.challenger()) {
 			1 => user1_capacity += challenge_ckb,
 			2 => user2_capacity += challenge_ckb,
-			_ => return Err(anyhow!("broken channel with wrong challenge data format"))
+			_ => return Err(KabletopError::BrokenChannel("wrong challenge data format"))
 		}
 	}
-	println!("channel_ckb = {}, challenge_ckb = {}, user1_capacity = {}, user2_capacity = {}",
-		channel_ckb, challenge_ckb, user1_capacity, user2_capacity);
+	tracing::debug!(channel_ckb, challenge_ckb, user1_capacity, user2_capacity, "split channel capacity");
     let outputs = 
 		vec![(&kabletop_args.user1_pkhash(), user1_capacity), (&kabletop_args.user2_pkhash(), user2_capacity)]
 			.iter()
@@ -706,7 +1633,7 @@ pub async fn build_tx_close_channel(
         .outputs_data(vec![Bytes::default(), Bytes::default()].pack())
         .build();
     tx = helper::complete_tx_with_sighash_cells(tx, &keystore::USER_PUBHASH, helper::fee("0.1")).await?;
-    tx = helper::add_code_celldep(tx, OutPoint::new(_C.kabletop.tx_hash.clone(), 0));
+    tx = helper::add_contract_celldep(tx, &_C.kabletop).await?;
 	for luacode in &_C.luacodes {
 		tx = helper::add_code_celldep(tx, OutPoint::new(luacode.tx_hash.clone(), 0));
 	}
@@ -723,7 +1650,11 @@ pub async fn build_tx_close_channel(
 mod test {
     use ckb_sdk::rpc::HttpRpcClient;
     use futures::executor::block_on;
-    use ckb_types::core::TransactionView;
+    use ckb_types::{
+        prelude::*, core::{
+            TransactionView, Capacity
+        }, packed::CellOutput
+    };
     use ckb_jsonrpc_types::{
 		TransactionView as JsonTxView, OutputsValidator
 	};
@@ -731,8 +1662,9 @@ mod test {
     use crate::{
         config::VARS as _C,
 		ckb::wallet::keystore,
+		ckb::rpc::methods as rpc,
         ckb::transaction::{
-            builder, helper, channel::interact, channel::protocol
+            builder, helper, swap, sale, channel::interact, channel::protocol
         }
     };
 	use molecule::prelude::{
@@ -759,6 +1691,15 @@ mod test {
         }
     }
 
+    const REVEAL_SECRET: [u8; 32] = [7u8; 32];
+
+    // the second devnet user, who is the counterparty of USER_PRIVKEY in reveal, swap and sale tests
+    fn user2_privkey() -> Privkey {
+        let byte32 = helper::blake256_to_byte32("d44955b4770247b233c284268c961085e622febb61d364c9a5cabe0c238f08d4")
+            .expect("blake2b_256 to [u8; 32]");
+        Privkey::from(ckb_types::H256(byte32))
+    }
+
     fn write_tx_to_file(tx: TransactionView, path: String) {
         let tx = JsonTxView::from(tx);
         let json = serde_json::to_string_pretty(&tx).expect("jsonify");
@@ -781,32 +1722,70 @@ mod test {
 
     #[test]
     fn test_build_tx_compose_nft() {
-        let tx = block_on(builder::build_tx_compose_nft(helper::fee("100").as_u64(), 3, default_nfts())).expect("compose nft");
+        let tx = block_on(builder::build_tx_compose_nft(0, helper::fee("100").as_u64(), 3, default_nfts())).expect("compose nft");
         send_transaction(tx, "compose_nft");
     }
 
     #[test]
     fn test_build_tx_create_nft_store() {
-        let tx = block_on(builder::build_tx_create_nft_store()).expect("create nft store");
+        let tx = block_on(builder::build_tx_create_nft_store(0)).expect("create nft store");
         send_transaction(tx, "create_nft_store");
     }
 
     #[test]
     fn test_build_tx_purchase_nft_package() {
-        let tx = block_on(builder::build_tx_purchase_nft_package(1)).expect("purchase nft package");
+        let tx = block_on(builder::build_tx_purchase_nft_package(0, 1)).expect("purchase nft package");
         send_transaction(tx, "purchase_nft_package");
     }
 
     #[test]
     fn test_build_tx_reveal_nft_package() {
-        let tx = block_on(builder::build_tx_reveal_nft_package()).expect("reveal nft package");
+        let tx = block_on(builder::build_tx_reveal_nft_package(0, None, None)).expect("reveal nft package");
         send_transaction(tx, "reveal_nft_package");
     }
 
+    #[test]
+    fn test_build_tx_reveal_nft_package_as_gift() {
+        let friend_privkey = user2_privkey();
+        let friend_lock = helper::sighash_script(&helper::privkey_to_pkhash(&friend_privkey)[..]).expect("sighash script");
+        let tx = block_on(builder::build_tx_reveal_nft_package(0, Some(friend_lock), Some(&friend_privkey)))
+            .expect("reveal nft package as gift");
+        send_transaction(tx, "reveal_nft_package_as_gift");
+    }
+
+    #[test]
+    fn test_build_tx_purchase_nft_package_with_commitment() {
+        let commitment = helper::reveal_commitment(&REVEAL_SECRET);
+        let tx = block_on(builder::build_tx_purchase_nft_package_with_commitment(0, 1, commitment)).expect("purchase nft package");
+        send_transaction(tx, "purchase_nft_package_with_commitment");
+    }
+
+    #[test]
+    fn test_build_tx_reveal_nft_package_with_secret() {
+        let tx = block_on(builder::build_tx_reveal_nft_package_with_secret(0, REVEAL_SECRET, None, None, 60)).expect("reveal nft package");
+        send_transaction(tx, "reveal_nft_package_with_secret");
+    }
+
+    #[test]
+    fn test_build_tx_cancel_nft_purchase() {
+        let commitment = helper::reveal_commitment(&REVEAL_SECRET);
+        let tx = block_on(builder::build_tx_purchase_nft_package_with_commitment(0, 1, commitment)).expect("purchase nft package");
+        let tx_hash = rpc::send_transaction(tx.data()).expect("send purchase");
+        block_on(rpc::wait_transaction_committed(tx_hash, 60)).expect("purchase committed");
+        let tx = block_on(builder::build_tx_cancel_nft_purchase(0)).expect("cancel nft purchase");
+        send_transaction(tx, "cancel_nft_purchase");
+    }
+
+    #[test]
+    fn test_build_tx_close_nft_store() {
+        let tx = block_on(builder::build_tx_close_nft_store(0)).expect("close nft store");
+        send_transaction(tx, "close_nft_store");
+    }
+
     #[test]
     fn test_build_tx_discard_nft() {
 		let discard = vec![helper::blake160(&[3u8])];
-        let tx = block_on(builder::build_tx_discard_nft(discard)).expect("discard nft");
+        let tx = block_on(builder::build_tx_discard_nft(discard, vec![])).expect("discard nft");
         send_transaction(tx, "discard_nft");
     }
 
@@ -814,10 +1793,35 @@ mod test {
     fn test_build_tx_transfer_nft() {
 		let transfer = vec![helper::blake160(&[3u8])];
 		let receiver = helper::blake160_to_byte20("b30e7cbeeb037e5d1f7e1939f733abed8d816db0").expect("blake160 to [u8; 20]");
-        let tx = block_on(builder::build_tx_transfer_nft(transfer, receiver)).expect("transfer nft");
+        let tx = block_on(builder::build_tx_transfer_nft(transfer, helper::sighash_script(&receiver).expect("sighash script"), vec![])).expect("transfer nft");
         send_transaction(tx, "transfer_nft");
     }
 
+    #[test]
+    fn test_build_tx_transfer_nft_batch() {
+        let transfers = vec![
+            (helper::sighash_script(&keystore::COMPOSER_PUBHASH[..]).expect("sighash script"), vec![helper::blake160(&[1u8])]),
+            (helper::sighash_script(&[0u8; 20]).expect("sighash script"), vec![helper::blake160(&[2u8]), helper::blake160(&[3u8])])
+        ];
+        let (tx, left_transfers) = block_on(builder::build_tx_transfer_nft_batch(transfers, vec![])).expect("transfer nft batch");
+        assert!(left_transfers.is_empty());
+        send_transaction(tx, "transfer_nft_batch");
+    }
+
+    #[test]
+    fn test_build_tx_merge_nft_cells() {
+        let tx = block_on(builder::build_tx_merge_nft_cells(&keystore::USER_PRIVKEY, 100, vec![])).expect("merge nft cells");
+        send_transaction(tx, "merge_nft_cells");
+    }
+
+    #[test]
+    fn test_build_tx_split_nft_cells() {
+        let groups = vec![vec![helper::blake160(&[1u8])], vec![helper::blake160(&[2u8])]];
+        let group_lock = helper::sighash_script(&keystore::USER_PUBHASH[..]).expect("sighash script");
+        let tx = block_on(builder::build_tx_split_nft_cells(&keystore::USER_PRIVKEY, groups, group_lock, vec![])).expect("split nft cells");
+        send_transaction(tx, "split_nft_cells");
+    }
+
     #[test]
     fn test_build_tx_issue_nft() {
 		let issue = default_nfts()
@@ -825,10 +1829,67 @@ mod test {
 			.map(|&(nft, _)| nft)
 			.collect::<Vec<_>>();
 		let receiver = helper::blake160_to_byte20("b30e7cbeeb037e5d1f7e1939f733abed8d816db0").expect("blake160 to [u8; 20]");
-        let tx = block_on(builder::build_tx_issue_nft(issue, receiver)).expect("issue nft");
+        let tx = block_on(builder::build_tx_issue_nft(0, issue, helper::sighash_script(&receiver).expect("sighash script"))).expect("issue nft");
         send_transaction(tx, "issue_nft");
     }
 
+    #[test]
+    fn test_build_tx_airdrop_nft() {
+		let receiver = helper::blake160_to_byte20("b30e7cbeeb037e5d1f7e1939f733abed8d816db0").expect("blake160 to [u8; 20]");
+        let airdrops = vec![
+            (helper::sighash_script(&receiver).expect("sighash script"), vec![default_nfts()[0].0]),
+            (helper::sighash_script(&keystore::USER_PUBHASH[..]).expect("sighash script"), vec![default_nfts()[0].0, default_nfts()[1].0])
+        ];
+        let tx = block_on(builder::build_tx_airdrop_nft(0, airdrops, String::from("launch airdrop"))).expect("airdrop nft");
+        assert_eq!(helper::AirdropRecord::from_tx(&tx).map(|record| record.nft_count), Some(3));
+        send_transaction(tx, "airdrop_nft");
+    }
+
+    #[test]
+    fn test_build_tx_withdraw_revenue() {
+		let receiver = helper::sighash_script(&keystore::COMPOSER_PUBHASH[..]).expect("sighash script");
+        let tx = block_on(builder::build_tx_withdraw_revenue(vec![], receiver)).expect("withdraw revenue");
+        send_transaction(tx, "withdraw_revenue");
+    }
+
+    #[test]
+    fn test_store_revenue() {
+        let composer = [1u8; 20];
+        let payment_script = helper::payment_script(composer.to_vec());
+        // payment args are made by hand as payment_args does, which requires feature "multi_series" for series 3
+        let wallet = |pkhash: [u8; 20], series: u8, capacity: u64| {
+            let mut args = pkhash.to_vec();
+            if series > 0 {
+                args.push(series);
+            }
+            CellOutput::new_builder()
+                .lock(helper::wallet_script(composer.to_vec()))
+                .type_(Some(helper::payment_script(args)).pack())
+                .capacity(capacity.pack())
+                .build()
+        };
+        let occupied = wallet([2u8; 20], 3, 0).occupied_capacity(Capacity::bytes(1).unwrap()).unwrap().as_u64();
+        let revenue = helper::fee("300").as_u64();
+
+        // an idle store gives out all capacity above occupied, and keeps its series
+        let (series, withdrawn, output) = builder::store_revenue(&wallet([2u8; 20], 3, occupied + revenue), &[0], &payment_script, &composer, &[])
+            .expect("store revenue")
+            .expect("idle store");
+        assert_eq!((series, withdrawn), (3, revenue));
+        assert_eq!(Unpack::<u64>::unpack(&output.capacity()), occupied);
+        assert_eq!(output.type_().to_opt(), wallet([2u8; 20], 3, 0).type_().to_opt());
+
+        // stores pending reveal, without revenue, not chosen, or of composer itself are skipped
+        let store_revenue = |output: &CellOutput, data: &[u8], stores: &[[u8; 20]]| {
+            builder::store_revenue(output, data, &payment_script, &composer, stores).expect("store revenue")
+        };
+        assert!(store_revenue(&wallet([2u8; 20], 0, occupied + revenue), &[1], &[]).is_none());
+        assert!(store_revenue(&wallet([2u8; 20], 3, occupied), &[0], &[]).is_none());
+        assert!(store_revenue(&wallet([2u8; 20], 0, occupied + revenue), &[0], &[[4u8; 20]]).is_none());
+        assert!(store_revenue(&wallet([2u8; 20], 0, occupied + revenue), &[0], &[[4u8; 20], [2u8; 20]]).is_some());
+        assert!(store_revenue(&wallet(composer, 0, occupied + revenue), &[0], &[]).is_none());
+    }
+
     #[test]
     fn test_build_tx_open_channel() {
         let user1_privkey = keystore::USER_PRIVKEY.clone();
@@ -849,10 +1910,12 @@ mod test {
         };
 
         // user1 prepare
-        let tx = block_on(interact::prepare_channel_tx(staking_ckb, bet_ckb, deck_size, user1_nfts.clone(), user1_pkhash.clone(), vec![]))
+        let user1 = interact::ChannelUser { pkhash: user1_pkhash, nfts: user1_nfts.clone(), owner_locks: vec![] };
+        let tx = block_on(interact::prepare_channel_tx(staking_ckb, bet_ckb, deck_size, user1, vec![]))
             .expect("prepare_channel_tx");
         // user2 complete
-        let tx = block_on(interact::complete_channel_tx(tx, staking_ckb, bet_ckb, deck_size, user2_nfts.clone(), user2_pkhash.clone(), vec![]))
+        let user2 = interact::ChannelUser { pkhash: user2_pkhash, nfts: user2_nfts.clone(), owner_locks: vec![] };
+        let tx = block_on(interact::complete_channel_tx(tx, staking_ckb, bet_ckb, deck_size, user2, vec![]))
             .expect("complete_channel_tx");
         // user2 sign
         let tx = interact::sign_channel_tx(tx, staking_ckb, bet_ckb, deck_size, user2_nfts, &user2_privkey)
@@ -864,6 +1927,74 @@ mod test {
         send_transaction(tx, "open_channel");
     }
 
+    #[test]
+    fn test_build_tx_swap_nft() {
+        let seller_privkey = keystore::USER_PRIVKEY.clone();
+        let buyer_privkey = user2_privkey();
+        let seller_pkhash = *keystore::USER_PUBHASH;
+        let buyer_pkhash = helper::privkey_to_pkhash(&buyer_privkey);
+        let price_ckb = helper::fee("300").as_u64();
+        let nfts = vec![default_nfts()[1].0];
+
+        // seller prepare
+        let tx = block_on(swap::prepare_swap_tx(nfts.clone(), price_ckb, seller_pkhash, vec![], buyer_pkhash))
+            .expect("prepare_swap_tx");
+        // buyer complete
+        let tx = block_on(swap::complete_swap_tx(tx, nfts.clone(), price_ckb, buyer_pkhash))
+            .expect("complete_swap_tx");
+        // buyer sign
+        let tx = swap::sign_swap_tx(tx, nfts.clone(), price_ckb, &buyer_privkey, vec![]).expect("buyer sign_swap_tx");
+        // seller sign
+        let tx = swap::sign_swap_tx(tx, nfts, price_ckb, &seller_privkey, vec![]).expect("seller sign_swap_tx");
+
+        send_transaction(tx, "swap_nft");
+    }
+
+    #[test]
+    fn test_build_tx_publish_nft_metadata() {
+        let mut registry = helper::NftRegistry::new();
+        for (i, (nft, _)) in default_nfts().into_iter().enumerate() {
+            registry.insert(nft, helper::NftMetadata {
+                name:        format!("card #{}", i + 1),
+                description: None,
+                image:       None,
+                attributes:  Default::default()
+            });
+        }
+        let tx = block_on(builder::build_tx_publish_nft_metadata(&registry, None)).expect("build_tx_publish_nft_metadata");
+        send_transaction(tx, "publish_nft_metadata");
+    }
+
+    #[test]
+    fn test_build_tx_list_nft_offer() {
+        let price_ckb = helper::fee("300").as_u64();
+        let tx = block_on(sale::build_tx_list_nft_offer(&keystore::USER_PRIVKEY, vec![default_nfts()[2].0], price_ckb, vec![]))
+            .expect("build_tx_list_nft_offer");
+        send_transaction(tx, "list_nft_offer");
+    }
+
+    #[test]
+    fn test_build_tx_fill_nft_offer() {
+        let buyer_privkey = user2_privkey();
+        let buyer_lock = helper::sighash_script(&helper::privkey_to_pkhash(&buyer_privkey)[..]).expect("sighash script");
+        let seller_lock = helper::sighash_script(&keystore::USER_PUBHASH[..]).expect("sighash script");
+        let offers = block_on(rpc::get_nft_offers(*keystore::COMPOSER_PUBHASH, Some(seller_lock), 10))
+            .expect("get_nft_offers");
+        let offer = offers.first().expect("no nft offer");
+        let tx = block_on(sale::build_tx_fill_nft_offer(&buyer_privkey, offer, buyer_lock)).expect("build_tx_fill_nft_offer");
+        send_transaction(tx, "fill_nft_offer");
+    }
+
+    #[test]
+    fn test_build_tx_cancel_nft_offer() {
+        let seller_lock = helper::sighash_script(&keystore::USER_PUBHASH[..]).expect("sighash script");
+        let offers = block_on(rpc::get_nft_offers(*keystore::COMPOSER_PUBHASH, Some(seller_lock), 10))
+            .expect("get_nft_offers");
+        let offer = offers.first().expect("no nft offer");
+        let tx = block_on(sale::build_tx_cancel_nft_offer(&keystore::USER_PRIVKEY, offer)).expect("build_tx_cancel_nft_offer");
+        send_transaction(tx, "cancel_nft_offer");
+    }
+
 	#[test]
 	fn test_build_tx_close_channel() {
 		// prepare kabletop script
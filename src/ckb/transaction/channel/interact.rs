@@ -1,20 +1,19 @@
 use crate::{
-    config::VARS as _C, ckb::{
+    config::VARS as _C, error::{
+        Result, KabletopError
+    }, ckb::{
         rpc::methods as rpc, wallet::{
             keystore, signer
         }, transaction::{
-            genesis::GENESIS as _G, helper, channel::protocol::*
+            genesis::genesis, helper, channel::protocol::*
         }
     }
 };
-use anyhow::{
-    Result, anyhow
-};
 use ckb_types::{
     prelude::*, bytes::Bytes, core::{
         Capacity, TransactionBuilder, TransactionView
     }, packed::{
-        CellOutput, OutPoint, Byte32
+        CellOutput, Byte32, Script
     }
 };
 use molecule::{
@@ -36,22 +35,38 @@ use std::convert::TryInto;
 * 	  none
 * lock:
 * 	  code_hash = kabletop_contract 
-* 	  hash_type = data
+* 	  hash_type = data | data1 | type
 * 	  args 	    = staking_ckb(u64) | deck_size(u8) | begin_blocknumber(u64) | lock_code_hash(blake256) 
 * 	  			  | user1_pkhash(blake160) | user1_nfts(vec<blake160>) | user2_pkhash(blake160) | user2_nfts(vec<blake160>)
 * type:
 * 	  any
+*
+* nft cells of each user are collected under the sighash lock of user and [owner_locks], each nft change cell goes back
+* to the lock of the nft cells it comes from, and sign_channel_tx only signs the sighash group, so the groups under
+* [owner_locks] are left for each user to sign
 */
 
+// the part of one user in kabletop tx, [nfts] are the deck of user which are collected under the sighash lock of
+// [pkhash] and [owner_locks]
+#[derive(Clone, Debug)]
+pub struct ChannelUser {
+    pub pkhash:      [u8; 20],
+    pub nfts:        Vec<[u8; 20]>,
+    pub owner_locks: Vec<Script>
+}
+
 // prepare kabletop tx with user1-part filled
+#[tracing::instrument(skip(user, hashes), fields(nfts = user.nfts.len()))]
 pub async fn prepare_channel_tx(
-    staking_ckb: u64, bet_ckb: u64, deck_size: u8, nfts: Vec<[u8; 20]>, pkhash: [u8; 20], hashes: Vec<Byte32>
+    staking_ckb: u64, bet_ckb: u64, deck_size: u8, user: ChannelUser, hashes: Vec<Byte32>
 ) -> Result<TransactionView> {
+    let ChannelUser { pkhash, nfts, owner_locks } = user;
+
     // prepare lock_args
     let block_number = rpc::get_tip_block_number()?;
-    let sighash_hash = _G.sighash_script.code_hash().clone();
+    let sighash_hash = genesis()?.sighash_script.code_hash();
     if deck_size < nfts.len() as u8 {
-        return Err(anyhow!("number of nft beyond specified deck size"));
+        return Err(KabletopError::DeckSizeExceeded);
     }
     let kabletop_args = Args::new_builder()
         .user_staking_ckb(staking_ckb.into())
@@ -71,10 +86,11 @@ pub async fn prepare_channel_tx(
 	let half_capacity = Capacity::shannons(bet_ckb + staking_ckb);
 	let required_capacity: Capacity = output.capacity().unpack();
 	if required_capacity.as_u64() > half_capacity.as_u64() * 2 {
-		return Err(anyhow!(
-			format!("bet and staking ckb ({}) cannot cover half of kabletop occupied capacity ({})",
-				half_capacity.as_u64() as f64 / 100_000_000.0, required_capacity.as_u64() as f64 / 100_000_000.0)
-		));
+		// each user offers bet and staking ckb for half of the occupied capacity
+		return Err(KabletopError::InsufficientCapacity {
+			required:  required_capacity.as_u64().div_ceil(2),
+			available: half_capacity.as_u64()
+		});
 	} else {
 		output = output
 			.as_builder()
@@ -87,31 +103,39 @@ pub async fn prepare_channel_tx(
         .output(output)
         .output_data(Bytes::from(vec![]).pack())
         .build();
-    let tx = helper::complete_tx_with_nft_cells(tx, &pkhash, &keystore::COMPOSER_PUBHASH, nfts, false).await?;
+    let owner_locks = helper::owner_locks(&pkhash[..], &owner_locks)?;
+    let tx = helper::complete_tx_with_owned_nft_cells(tx, &owner_locks, &keystore::COMPOSER_PUBHASH, nfts, false).await?;
     let tx = helper::complete_tx_with_sighash_cells(tx, &pkhash, helper::fee("0.05")).await?;
-    let tx = helper::add_code_celldep(tx, OutPoint::new(_C.kabletop.tx_hash.clone(), 0));
+    let tx = helper::add_contract_celldep(tx, &_C.kabletop).await?;
 
     Ok(tx)
 }
 
 // complete kabeltop tx with user2-part filled
+#[tracing::instrument(skip(tx, user, hashes), fields(tx_hash = %tx.hash(), nfts = user.nfts.len()))]
 pub async fn complete_channel_tx(
-    tx: TransactionView, staking_ckb: u64, bet_ckb: u64, deck_size: u8, nfts: Vec<[u8; 20]>, pkhash: [u8; 20], hashes: Vec<Byte32>
+    tx: TransactionView, staking_ckb: u64, bet_ckb: u64, deck_size: u8, user: ChannelUser, hashes: Vec<Byte32>
 ) -> Result<TransactionView> {
+    let ChannelUser { pkhash, nfts, owner_locks } = user;
+
     // check and complete kabletop args
     let mut tx_outputs: Vec<CellOutput> = tx.outputs().into_iter().map(|output| output).collect();
-    let output = tx_outputs.first().ok_or(anyhow!("tx's output is empty"))?;
+    let output = tx_outputs.first().ok_or_else(|| KabletopError::InvalidTransaction(String::from("tx's output is empty")))?;
     let kabletop_args = {
         let args: Bytes = output.lock().args().unpack();
         Args::new_unchecked(MolBytes::from(args.to_vec()))
     };
-    if u64::from(kabletop_args.user_staking_ckb())    != staking_ckb
-        || u8::from(kabletop_args.user_deck_size())   != deck_size 
-		|| Vec::from(kabletop_args.lua_code_hashes()) != hashes {
-        return Err(anyhow!("some of kabletop args mismatched"));
+    if u64::from(kabletop_args.user_staking_ckb()) != staking_ckb {
+        return Err(KabletopError::ArgsMismatch { field: "user_staking_ckb" });
+    }
+    if u8::from(kabletop_args.user_deck_size()) != deck_size {
+        return Err(KabletopError::ArgsMismatch { field: "user_deck_size" });
+    }
+    if Vec::from(kabletop_args.lua_code_hashes()) != hashes {
+        return Err(KabletopError::ArgsMismatch { field: "lua_code_hashes" });
     }
     if deck_size < nfts.len() as u8 {
-        return Err(anyhow!("number of nft beyond specified deck size"));
+        return Err(KabletopError::DeckSizeExceeded);
     }
     let kabletop_args = kabletop_args
         .as_builder()
@@ -126,7 +150,9 @@ pub async fn complete_channel_tx(
         u64::from_le_bytes(bytes)
     };
     if capacity != staking_ckb + bet_ckb {
-        return Err(anyhow!("quantity of output capacity mismatched"));
+        return Err(KabletopError::InvalidTransaction(
+            format!("kabletop output capacity {} isn't bet and staking ckb {}", capacity, staking_ckb + bet_ckb)
+        ));
     }
     tx_outputs[0] = output
         .clone()
@@ -140,18 +166,20 @@ pub async fn complete_channel_tx(
         .as_advanced_builder()
         .set_outputs(tx_outputs)
         .build();
-    let tx = helper::complete_tx_with_nft_cells(tx, &pkhash, &keystore::COMPOSER_PUBHASH, nfts, false).await?;
+    let owner_locks = helper::owner_locks(&pkhash[..], &owner_locks)?;
+    let tx = helper::complete_tx_with_owned_nft_cells(tx, &owner_locks, &keystore::COMPOSER_PUBHASH, nfts, false).await?;
     let tx = helper::complete_tx_with_sighash_cells(tx, &pkhash, helper::fee("0.05")).await?;
 
     Ok(tx)
 }
 
 // check kabletop args and sign channel tx
+#[tracing::instrument(skip(tx, nfts, privkey), fields(tx_hash = %tx.hash(), nfts = nfts.len()))]
 pub fn sign_channel_tx(
     tx: TransactionView, staking_ckb: u64, bet_ckb: u64, deck_size: u8, nfts: Vec<[u8; 20]>, privkey: &Privkey
 ) -> Result<TransactionView> {
     // check kabletop args
-    let output = tx.output(0).ok_or(anyhow!("tx's output is empty"))?;
+    let output = tx.output(0).ok_or_else(|| KabletopError::InvalidTransaction(String::from("tx's output is empty")))?;
     let kabletop_args = {
         let args: Bytes = output.lock().args().unpack();
         Args::new_unchecked(MolBytes::from(args.to_vec()))
@@ -159,13 +187,16 @@ pub fn sign_channel_tx(
     let pkhash = helper::privkey_to_pkhash(&privkey);
     let user1_pkhash = <[u8; 20]>::from(kabletop_args.user1_pkhash());
     let user2_pkhash = <[u8; 20]>::from(kabletop_args.user2_pkhash());
-    let mut user1_nfts = Vec::from(kabletop_args.user1_nfts());
-    let mut user2_nfts = Vec::from(kabletop_args.user2_nfts());
-    if u64::from(kabletop_args.user_staking_ckb())  != staking_ckb
-        || u8::from(kabletop_args.user_deck_size()) != deck_size
-        || (user1_pkhash == pkhash && user1_nfts != nfts)
-        || (user2_pkhash == pkhash && user2_nfts != nfts) {
-        return Err(anyhow!("some of kabletop args mismatched"));
+    let user1_nfts = Vec::from(kabletop_args.user1_nfts());
+    let user2_nfts = Vec::from(kabletop_args.user2_nfts());
+    if u64::from(kabletop_args.user_staking_ckb()) != staking_ckb {
+        return Err(KabletopError::ArgsMismatch { field: "user_staking_ckb" });
+    }
+    if u8::from(kabletop_args.user_deck_size()) != deck_size {
+        return Err(KabletopError::ArgsMismatch { field: "user_deck_size" });
+    }
+    if (user1_pkhash == pkhash && user1_nfts != nfts) || (user2_pkhash == pkhash && user2_nfts != nfts) {
+        return Err(KabletopError::ArgsMismatch { field: "nfts" });
     }
 
     // check kabletop output capacity
@@ -174,12 +205,14 @@ pub fn sign_channel_tx(
         ckb.as_u64()
     };
     if capacity != (staking_ckb + bet_ckb) * 2 {
-        return Err(anyhow!("kabletop output capacity is incorrect"));
+        return Err(KabletopError::InvalidTransaction(
+            format!("kabletop output capacity {} isn't double bet and staking ckb {}", capacity, (staking_ckb + bet_ckb) * 2)
+        ));
     }
 
     // check wether two nft lists from kabletop args match both their nft cells'
-    let user1_lock_script = helper::sighash_script(&user1_pkhash[..]);
-    let user2_lock_script = helper::sighash_script(&user2_pkhash[..]);
+    let user1_lock_script = helper::sighash_script(&user1_pkhash[..])?;
+    let user2_lock_script = helper::sighash_script(&user2_pkhash[..])?;
     let type_script = {
         let wallet = helper::wallet_script(keystore::COMPOSER_PUBHASH.clone().to_vec());
         helper::nft_script(wallet.calc_script_hash().raw_data().to_vec())
@@ -207,17 +240,14 @@ pub fn sign_channel_tx(
                 }
             }
         });
-    helper::blake160_intersect(&mut user1_nfts, &mut user1_cell_nfts);
-    helper::blake160_intersect(&mut user2_nfts, &mut user2_cell_nfts);
-    if user1_nfts.len() > 0 || user2_nfts.len() > 0 {
-        return Err(anyhow!("some of two users haven't supplied correct nft cells"));
+    let user1_covered = helper::NftBag::from(&user1_nfts[..]).is_subset(&helper::NftBag::from(&user1_cell_nfts[..]));
+    let user2_covered = helper::NftBag::from(&user2_nfts[..]).is_subset(&helper::NftBag::from(&user2_cell_nfts[..]));
+    if !user1_covered || !user2_covered {
+        return Err(KabletopError::NftCellsMismatch);
     }
 
     // sign tx
-    let tx = signer::sign(tx, &privkey, vec![], Box::new(move |input| {
-        let bytes: Bytes = input.lock().args().unpack();
-        bytes.to_vec() == pkhash
-    }));
+    let tx = signer::sign_sighash(tx, privkey, vec![])?;
     Ok(tx)
 }
 
@@ -250,7 +280,7 @@ pub fn check_channel_round(
         };
         Ok(pkhash == expect_pkhash)
     } else {
-        // Err(anyhow!("import empty signed_rounds data"))
+        // Err(KabletopError::EmptyRounds)
 		Ok(true)
     }
 }
@@ -0,0 +1,276 @@
+use ckb_types::{
+    prelude::*, bytes::Bytes, H256, core::{
+        TransactionBuilder, TransactionView, Capacity, ScriptHashType
+    }, packed::{
+        CellOutput, CellInput, Byte32
+    }
+};
+use crate::{
+    error::{
+        Result, KabletopError
+    }, config::{
+        self, types::{
+            self as conf, ckb::Contract
+        }
+    }, ckb::{
+        transaction::helper, rpc::{
+            methods as rpc, types::{
+                SearchKey, ScriptType
+            }
+        }, wallet::{
+            signer, keystore
+        }
+    }
+};
+use ckb_hash::blake2b_256;
+use std::path::PathBuf;
+
+// the on-chain location of one deployed contract binary or luacode
+pub struct Deployment {
+    pub tx_hash:   Byte32,
+    pub data_hash: Byte32,
+    pub hash_type: ScriptHashType,
+    pub type_args: Option<[u8; 32]>
+}
+
+impl Deployment {
+    // the code_hash which scripts should use to reference this deployment
+    pub fn code_hash(&self) -> Byte32 {
+        match self.type_args {
+            Some(type_args) => helper::type_id_script(type_args.to_vec()).calc_script_hash(),
+            None            => self.data_hash.clone()
+        }
+    }
+
+    pub fn to_contract(&self) -> conf::Contract {
+        conf::Contract {
+            tx_hash:   hex::encode(self.tx_hash.raw_data()),
+            code_hash: hex::encode(self.code_hash().raw_data()),
+            hash_type: self.hash_type.into(),
+            type_args: self.type_args.map(hex::encode),
+            features:  vec![]
+        }
+    }
+
+    pub fn to_luacode(&self) -> conf::Luacode {
+        conf::Luacode {
+            tx_hash:   hex::encode(self.tx_hash.raw_data()),
+            data_hash: hex::encode(self.data_hash.raw_data())
+        }
+    }
+}
+
+/* CODE_CELL
+*
+* to help composers deploy a contract binary or a luacode into a code cell, which must be the first output of the
+* deployment transaction so that the configured tx_hash could reference it as a cell dep
+*
+* data:
+*     binary
+* lock:
+*     code_hash = sighash_blake160
+*     hash_type = type
+*     args      = composer_pubkey_blake160
+* type:
+*     none, or
+*     code_hash = TYPE_ID
+*     hash_type = type
+*     args      = blake2b_256(first_input | output_index(u64))
+*/
+#[tracing::instrument(skip(binary), fields(size = binary.len()))]
+pub async fn build_tx_deploy_code(binary: Bytes, with_type_id: bool) -> Result<TransactionView> {
+    // prepare output cell with a placeholder type-id args which has the same size as the real one
+    let mut output = CellOutput::new_builder()
+        .lock(helper::sighash_script(&keystore::COMPOSER_PUBHASH[..])?);
+    if with_type_id {
+        output = output.type_(Some(helper::type_id_script(vec![0u8; 32])).pack());
+    }
+    let output = output.build_exact_capacity(Capacity::bytes(binary.len())?)?;
+
+    // prepare tx
+    let tx = TransactionBuilder::default()
+        .output(output)
+        .output_data(binary.pack())
+        .build();
+
+    // complete tx and fill the real type-id args which depends on the first input
+    let mut tx = helper::complete_tx_with_sighash_cells(tx, &keystore::COMPOSER_PUBHASH, helper::fee("0.1")).await?;
+    if with_type_id {
+        let first_input = tx
+            .inputs()
+            .get(0)
+            .ok_or_else(|| KabletopError::InvalidTransaction(String::from("deployment tx has no inputs")))?;
+        let type_args = helper::calc_type_id_args(&first_input, 0);
+        let mut outputs = tx.outputs().into_iter().collect::<Vec<_>>();
+        outputs[0] = outputs[0]
+            .clone()
+            .as_builder()
+            .type_(Some(helper::type_id_script(type_args.to_vec())).pack())
+            .build();
+        tx = tx
+            .as_advanced_builder()
+            .set_outputs(outputs)
+            .build();
+    }
+
+    // sign tx
+    let tx = signer::sign(tx, &keystore::COMPOSER_PRIVKEY, vec![], Box::new(|_| true));
+    Ok(tx)
+}
+
+/* UPGRADE_CODE_CELL
+*
+* to help composers replace the binary of a contract deployed with type-id, the type-id script is kept so that
+* every script referencing the contract by hash_type "type" needs no change
+*
+* // INPUT_CELL
+* on-chain code_cell found by type-id
+*
+* // OUTPUT_CELL
+* data:
+*     new binary
+* lock:
+*     (same as code_cell)
+* type:
+*     (same as code_cell)
+*/
+#[tracing::instrument(skip_all, fields(size = binary.len()))]
+pub async fn build_tx_upgrade_code(contract: &Contract, binary: Bytes) -> Result<TransactionView> {
+    // search the code cell by type-id
+    let type_id = contract.type_id.clone().ok_or(KabletopError::ContractWithoutTypeId)?;
+    let search_key = SearchKey::new(type_id.clone().into(), ScriptType::Type);
+    let code_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
+    if code_cell.is_empty() {
+        return Err(KabletopError::ContractNotFound(format!("type-id {}", type_id.calc_script_hash())));
+    }
+
+    // prepare input/output
+    let input = CellInput::new_builder()
+        .previous_output(code_cell[0].out_point.clone())
+        .build();
+    let output = code_cell[0].output
+        .clone()
+        .as_builder()
+        .build_exact_capacity(Capacity::bytes(binary.len())?)?;
+
+    // prepare tx
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .output(output)
+        .output_data(binary.pack())
+        .build();
+
+    // complete tx
+    let tx = helper::complete_tx_with_sighash_cells(tx, &keystore::COMPOSER_PUBHASH, helper::fee("0.1")).await?;
+
+    // sign tx
+    let tx = signer::sign(tx, &keystore::COMPOSER_PRIVKEY, vec![], Box::new(|_| true));
+    Ok(tx)
+}
+
+// deploy the binary file at [path] and wait until it's committed, hash_type "type" means deploying with type-id, and
+// "data" or "data1" only decides which ckb-vm version the scripts referencing it will run on
+#[tracing::instrument]
+pub async fn deploy_code(path: PathBuf, hash_type: ScriptHashType, timeout_secs: u64) -> Result<Deployment> {
+    let binary = Bytes::from(std::fs::read(&path)?);
+    let data_hash = Byte32::new(blake2b_256(&binary));
+    let with_type_id = hash_type == ScriptHashType::Type;
+    let tx = build_tx_deploy_code(binary, with_type_id).await?;
+    let type_args = tx
+        .output(0)
+        .and_then(|output| output.type_().to_opt())
+        .map(|script| {
+            let args: Bytes = script.args().unpack();
+            let mut type_args = [0u8; 32];
+            type_args.copy_from_slice(&args);
+            type_args
+        });
+    let tx_hash = rpc::send_transaction(tx.data())?;
+    rpc::wait_transaction_committed(tx_hash.clone(), timeout_secs).await?;
+    Ok(Deployment {
+        tx_hash: tx_hash.pack(), data_hash, hash_type, type_args
+    })
+}
+
+// deploy the binary file at [path] into the type-id code cell of [contract] and wait until it's committed
+#[tracing::instrument(skip(contract))]
+pub async fn upgrade_code(contract: &Contract, path: PathBuf, timeout_secs: u64) -> Result<Deployment> {
+    let binary = Bytes::from(std::fs::read(&path)?);
+    let data_hash = Byte32::new(blake2b_256(&binary));
+    let tx = build_tx_upgrade_code(contract, binary).await?;
+    let type_args = contract.type_id.as_ref().map(|script| {
+        let args: Bytes = script.args().unpack();
+        let mut type_args = [0u8; 32];
+        type_args.copy_from_slice(&args);
+        type_args
+    });
+    let tx_hash: H256 = rpc::send_transaction(tx.data())?;
+    rpc::wait_transaction_committed(tx_hash.clone(), timeout_secs).await?;
+    Ok(Deployment {
+        tx_hash: tx_hash.pack(), data_hash, hash_type: ScriptHashType::Type, type_args
+    })
+}
+
+// deploy named [contracts] ("nft", "wallet", "payment" or "kabletop") and [luacodes] one by one from local files, and
+// then write them all into "Kabletop.toml" (see config::CONFIG_PATH), which takes effect at the next start
+#[tracing::instrument(skip_all)]
+pub async fn deploy_and_update_config(
+    contracts: Vec<(&str, PathBuf, ScriptHashType)>, luacodes: Vec<PathBuf>, timeout_secs: u64
+) -> Result<()> {
+    let mut deployed_contracts = vec![];
+    for (name, path, hash_type) in contracts {
+        let deployment = deploy_code(path, hash_type, timeout_secs).await?;
+        deployed_contracts.push((name, deployment.to_contract()));
+    }
+    let mut deployed_luacodes = vec![];
+    for path in luacodes {
+        let deployment = deploy_code(path, ScriptHashType::Data, timeout_secs).await?;
+        deployed_luacodes.push(deployment.to_luacode());
+    }
+    config::update(config::CONFIG_PATH, deployed_contracts, deployed_luacodes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn deployment(hash_type: ScriptHashType, type_args: Option<[u8; 32]>) -> Deployment {
+        Deployment {
+            tx_hash:   [1u8; 32].pack(),
+            data_hash: [2u8; 32].pack(),
+            hash_type,
+            type_args
+        }
+    }
+
+    #[test]
+    fn test_deployment_code_hash() {
+        // a data deployment is referenced by its data hash, and a type-id one by the script hash of its type-id
+        let data = deployment(ScriptHashType::Data1, None);
+        assert_eq!(data.code_hash(), [2u8; 32].pack());
+        let type_id = deployment(ScriptHashType::Type, Some([3u8; 32]));
+        assert_eq!(type_id.code_hash(), helper::type_id_script(vec![3u8; 32]).calc_script_hash());
+        assert_ne!(type_id.code_hash(), type_id.data_hash);
+    }
+
+    #[test]
+    fn test_deployment_to_contract() {
+        let contract = deployment(ScriptHashType::Data, None).to_contract();
+        assert_eq!(contract.tx_hash, hex::encode([1u8; 32]));
+        assert_eq!(contract.code_hash, hex::encode([2u8; 32]));
+        assert!(contract.type_args.is_none() && contract.features.is_empty());
+
+        // a type-id deployment is written with hash_type "type" and the type-id args
+        let type_id = deployment(ScriptHashType::Type, Some([3u8; 32]));
+        let contract = type_id.to_contract();
+        assert_eq!(contract.code_hash, hex::encode(type_id.code_hash().raw_data()));
+        assert_eq!(contract.type_args, Some(hex::encode([3u8; 32])));
+        let content = toml::to_string(&contract).expect("serialize");
+        assert!(content.contains("hash_type = \"type\""));
+        let contract: conf::Contract = toml::from_str(&content).expect("parse");
+        assert_eq!(contract.type_args, Some(hex::encode([3u8; 32])));
+
+        let luacode = deployment(ScriptHashType::Data, None).to_luacode();
+        assert_eq!(luacode.data_hash, hex::encode([2u8; 32]));
+    }
+}
@@ -1,65 +1,138 @@
 use ckb_types::{
-    prelude::*,
+    prelude::*, h256, H256,
     core::{
-        DepType, ScriptHashType
+        BlockView, DepType, ScriptHashType
     },
     packed::{
-        CellDep, OutPoint, Transaction, Script
+        CellDep, OutPoint, Transaction, Script, Byte32
     },
 };
-use anyhow::{
-    Result, anyhow
+use ckb_chain_spec::ChainSpec;
+use ckb_resource::Resource;
+use crate::{
+    error::{
+        Result, KabletopError
+    }, config::{
+        VARS as _C, types::ckb::GenesisSource
+    }, ckb::rpc
 };
-use ckb_hash::new_blake2b;
-use crate::ckb::rpc;
+use std::{
+    fs, path::Path, sync::OnceLock
+};
+
+static GENESIS: OnceLock<Genesis> = OnceLock::new();
 
-lazy_static! {
-    pub static ref GENESIS: Genesis = get_genesis_from_block().expect("get genesis");
+// get genesis info loaded from [genesis] in Kabletop.toml at the first call, scripts built without genesis info can
+// never be unlocked, so a failed loading is returned as KabletopError::Genesis and tried again at the next call
+pub fn genesis() -> Result<&'static Genesis> {
+    if let Some(genesis) = GENESIS.get() {
+        return Ok(genesis);
+    }
+    let genesis = Genesis::load(&_C.genesis).map_err(|err| match err {
+        KabletopError::Genesis(_) => err,
+        err => KabletopError::Genesis(err.to_string())
+    })?;
+    Ok(GENESIS.get_or_init(|| genesis))
 }
 
+// system scripts are deployed with type-id in genesis block, so their type hashes are the same on every chain
+pub const SIGHASH_TYPE_HASH:  H256 = h256!("0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8");
+pub const MULTISIG_TYPE_HASH: H256 = h256!("0x5c5069eb0857efc65e1bca0c07df34c31663b3622fd3876c876320fc9634e2a8");
+
+pub const MAINNET_GENESIS_HASH:        H256 = h256!("0x92b197aa1fba0f63633922c61c92375c9c074a93e85963554f5499fe1450d0e5");
+pub const MAINNET_DEP_GROUP_TX_HASH:   H256 = h256!("0x71a7ba8fc96349fea0ed3a5c47992e3b4084b031a42264a018e0072e8172e46c");
+pub const TESTNET_GENESIS_HASH:        H256 = h256!("0x10639e0895502b5688a6be8cf69460d76541bfa4821629d86d62ba0aae3f9606");
+pub const TESTNET_DEP_GROUP_TX_HASH:   H256 = h256!("0xf8de3bb47d055cdf460d93a2a6e1b05f7432f9777c8c474abf4eec1d4aee5d37");
+
+// (transaction index, output index) of system cells in a standard genesis block
 const SIGHASH_OUTPUT:        (usize, usize) = (0, 1);
+const MULTISIG_OUTPUT:       (usize, usize) = (0, 4);
 const SIGHASH_GROUP_OUTPUT:  (usize, usize) = (1, 0);
 const MULTISIG_GROUP_OUTPUT: (usize, usize) = (1, 1);
 
 pub struct Genesis {
+    pub genesis_hash:     Byte32,
     pub sighash_script:   Script,
+    pub multisig_script:  Script,
     pub sighash_celldep:  CellDep,
-    pub multisig_celldep: CellDep,
+    pub multisig_celldep: CellDep
+}
+
+impl Genesis {
+    // load essential data like scripts and cell_deps of system cells from [source], only GenesisSource::Node and
+    // GenesisSource::Cached with a missing cache file need the ckb node
+    pub fn load(source: &GenesisSource) -> Result<Genesis> {
+        match source {
+            GenesisSource::Mainnet         => Ok(Genesis::from_constants(MAINNET_GENESIS_HASH, MAINNET_DEP_GROUP_TX_HASH)),
+            GenesisSource::Testnet         => Ok(Genesis::from_constants(TESTNET_GENESIS_HASH, TESTNET_DEP_GROUP_TX_HASH)),
+            GenesisSource::ChainSpec(path) => Genesis::from_block(get_genesis_from_spec(path)?),
+            GenesisSource::Cached(path)    => Genesis::from_block(get_genesis_from_cache(path)?),
+            GenesisSource::Node            => Genesis::from_block(rpc::methods::get_genesis_block()?.into_view())
+        }
+    }
+
+    fn from_constants(genesis_hash: H256, dep_group_tx_hash: H256) -> Genesis {
+        Genesis {
+            genesis_hash:     genesis_hash.pack(),
+            sighash_script:   build_type_script(SIGHASH_TYPE_HASH.pack()),
+            multisig_script:  build_type_script(MULTISIG_TYPE_HASH.pack()),
+            sighash_celldep:  build_celldep(dep_group_tx_hash.pack(), SIGHASH_GROUP_OUTPUT.1 as u32),
+            multisig_celldep: build_celldep(dep_group_tx_hash.pack(), MULTISIG_GROUP_OUTPUT.1 as u32)
+        }
+    }
+
+    // fetch some essential data like script and cell_deps from [block] which should be a genesis block
+    pub fn from_block(block: BlockView) -> Result<Genesis> {
+        let transaction = |(tx_index, _): (usize, usize)| {
+            block
+                .data()
+                .transactions()
+                .get(tx_index)
+                .ok_or_else(|| KabletopError::Genesis(format!("no transaction #{} found in genesis block", tx_index)))
+        };
+        let sighash_tx = transaction(SIGHASH_OUTPUT)?;
+        let multisig_tx = transaction(MULTISIG_OUTPUT)?;
+        let sighash_group_tx = transaction(SIGHASH_GROUP_OUTPUT)?;
+        let multisig_group_tx = transaction(MULTISIG_GROUP_OUTPUT)?;
+        let genesis = Genesis {
+            genesis_hash:     block.hash(),
+            sighash_script:   build_script(sighash_tx, SIGHASH_OUTPUT.1)?,
+            multisig_script:  build_script(multisig_tx, MULTISIG_OUTPUT.1)?,
+            sighash_celldep:  build_celldep(sighash_group_tx.calc_tx_hash(), SIGHASH_GROUP_OUTPUT.1 as u32),
+            multisig_celldep: build_celldep(multisig_group_tx.calc_tx_hash(), MULTISIG_GROUP_OUTPUT.1 as u32)
+        };
+        Ok(genesis)
+    }
+
+    pub fn is_mainnet(&self) -> bool {
+        self.genesis_hash == MAINNET_GENESIS_HASH.pack()
+    }
+}
+
+// build the genesis block from a devnet chain spec file at [path]
+fn get_genesis_from_spec(path: &Path) -> Result<BlockView> {
+    let spec = ChainSpec::load_from(&Resource::file_system(path.to_path_buf()))
+        .map_err(|err| KabletopError::Genesis(format!("load chain spec {}: {}", path.display(), err)))?;
+    spec.build_genesis().map_err(|err| KabletopError::Genesis(format!("build genesis from chain spec: {}", err)))
 }
 
-// fetch some essential data like script and cell_deps from gensis block
-fn get_genesis_from_block() -> Result<Genesis> {
-    let block = rpc::methods::get_genesis_block()?;
-    let sighash_tx = block
-        .transactions()
-        .get(SIGHASH_OUTPUT.0)
-        .ok_or_else(|| anyhow!("no sighash transaction found"))?;
-    let sighash_group_tx = block
-        .transactions()
-        .get(SIGHASH_GROUP_OUTPUT.0)
-        .ok_or_else(|| anyhow!("no sighash group transaction found"))?;
-    let multisig_group_tx = block
-        .transactions()
-        .get(MULTISIG_GROUP_OUTPUT.0)
-        .ok_or_else(|| anyhow!("no multisig group transaction found"))?;
-    let genesis = Genesis {
-        sighash_script:   build_script(sighash_tx, SIGHASH_OUTPUT.1)?,
-        sighash_celldep:  build_celldep(sighash_group_tx, SIGHASH_GROUP_OUTPUT.1 as u32),
-        multisig_celldep: build_celldep(multisig_group_tx, MULTISIG_GROUP_OUTPUT.1 as u32)
-    };
-    Ok(genesis)
+// read the genesis block from a json cache file at [path], the file will be fetched from ckb node and written if missing
+fn get_genesis_from_cache(path: &Path) -> Result<BlockView> {
+    if path.exists() {
+        let content = fs::read(path)?;
+        let block: ckb_jsonrpc_types::BlockView = serde_json::from_slice(&content)?;
+        Ok(block.into())
+    } else {
+        let block = rpc::methods::get_genesis_block()?.into_view();
+        let json = ckb_jsonrpc_types::BlockView::from(block.clone());
+        fs::write(path, serde_json::to_string_pretty(&json)?)?;
+        Ok(block)
+    }
 }
 
-fn build_celldep(tx: Transaction, tx_index: u32) -> CellDep {
-    let tx_hash = {
-        let mut hasher = new_blake2b();
-        hasher.update(tx.raw().as_slice());
-        let mut hash = [0u8; 32];
-        hasher.finalize(&mut hash);
-        hash
-    };
+fn build_celldep(tx_hash: Byte32, tx_index: u32) -> CellDep {
     let outpoint = OutPoint::new_builder()
-        .tx_hash(tx_hash.pack())
+        .tx_hash(tx_hash)
         .index(tx_index.pack())
         .build();
     CellDep::new_builder()
@@ -68,31 +141,60 @@ fn build_celldep(tx: Transaction, tx_index: u32) -> CellDep {
         .build()
 }
 
+fn build_type_script(type_hash: Byte32) -> Script {
+    Script::new_builder()
+        .code_hash(type_hash)
+        .hash_type(ScriptHashType::Type.into())
+        .build()
+}
+
 fn build_script(tx: Transaction, tx_index: usize) -> Result<Script> {
     let output = tx
         .raw()
         .outputs()
         .get(tx_index)
-        .ok_or_else(|| anyhow!("can't find cell output"))?;
+        .ok_or_else(|| KabletopError::Genesis(String::from("can't find cell output")))?;
     let type_hash = output
         .type_()
         .to_opt()
         .map(|script| script.calc_script_hash())
-        .ok_or_else(|| anyhow!("can't calc typescript hash"))?;
-    Ok(
-        Script::new_builder()
-            .code_hash(type_hash)
-            .hash_type(ScriptHashType::Type.into())
-            .build()
-    )
+        .ok_or_else(|| KabletopError::Genesis(String::from("can't calc typescript hash")))?;
+    Ok(build_type_script(type_hash))
 }
 
 #[cfg(test)]
 mod test {
-    use super::get_genesis_from_block;
+    use super::*;
+
+    fn bundled_genesis(spec: &str) -> Genesis {
+        let spec = ChainSpec::load_from(&Resource::bundled(String::from(spec))).expect("load bundled spec");
+        Genesis::from_block(spec.build_genesis().expect("build genesis")).expect("genesis from block")
+    }
+
+    fn assert_same_genesis(expected: Genesis, actual: Genesis) {
+        assert_eq!(expected.genesis_hash, actual.genesis_hash, "bad genesis hash");
+        assert_eq!(expected.sighash_script, actual.sighash_script, "bad sighash script");
+        assert_eq!(expected.multisig_script, actual.multisig_script, "bad multisig script");
+        assert_eq!(expected.sighash_celldep, actual.sighash_celldep, "bad sighash celldep");
+        assert_eq!(expected.multisig_celldep, actual.multisig_celldep, "bad multisig celldep");
+    }
 
     #[test]
     fn test_make_genesis() {
-        get_genesis_from_block().expect("test geting genesis");
+        Genesis::load(&GenesisSource::Node).expect("test geting genesis");
+    }
+
+    #[test]
+    fn test_mainnet_constants() {
+        let genesis = Genesis::load(&GenesisSource::Mainnet).expect("mainnet genesis");
+        assert!(genesis.is_mainnet());
+        assert_same_genesis(genesis, bundled_genesis("specs/mainnet.toml"));
+    }
+
+    #[test]
+    fn test_testnet_constants() {
+        let genesis = Genesis::load(&GenesisSource::Testnet).expect("testnet genesis");
+        assert!(!genesis.is_mainnet());
+        assert_same_genesis(genesis, bundled_genesis("specs/testnet.toml"));
     }
 }
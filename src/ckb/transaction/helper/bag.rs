@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap, iter::FromIterator
+};
+
+// a multiset of nfts which keeps the count of each nft blake160, all operations take linear time in the number of
+// distinct nfts
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NftBag {
+    counts: HashMap<[u8; 20], u32>
+}
+
+impl NftBag {
+    pub fn new() -> NftBag {
+        NftBag::default()
+    }
+
+    // collect nfts from nft cell [data] which is blake160 | blake160 | ...
+    pub fn from_bytes(data: &[u8]) -> NftBag {
+        data.chunks_exact(20)
+            .map(|nft| {
+                let mut blake160 = [0u8; 20];
+                blake160.copy_from_slice(nft);
+                blake160
+            })
+            .collect()
+    }
+
+    pub fn insert(&mut self, nft: [u8; 20]) {
+        *self.counts.entry(nft).or_default() += 1;
+    }
+
+    // remove one [nft] from bag, returns false if there's no [nft]
+    pub fn remove(&mut self, nft: &[u8; 20]) -> bool {
+        match self.counts.get_mut(nft) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                true
+            },
+            Some(_) => {
+                self.counts.remove(nft);
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn count(&self, nft: &[u8; 20]) -> u32 {
+        self.counts.get(nft).cloned().unwrap_or(0)
+    }
+
+    // the total number of nfts
+    pub fn len(&self) -> usize {
+        self.counts.values().map(|&count| count as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn counts(&self) -> &HashMap<[u8; 20], u32> {
+        &self.counts
+    }
+
+    // nfts from both bags, each of which takes the sum of counts
+    pub fn union(&self, other: &NftBag) -> NftBag {
+        let mut bag = self.clone();
+        for (nft, &count) in &other.counts {
+            *bag.counts.entry(*nft).or_default() += count;
+        }
+        bag
+    }
+
+    // nfts in both bags, each of which takes the smaller count
+    pub fn intersect(&self, other: &NftBag) -> NftBag {
+        let (small, large) = if self.counts.len() <= other.counts.len() { (self, other) } else { (other, self) };
+        let counts = small.counts
+            .iter()
+            .filter_map(|(nft, &count)| {
+                let count = count.min(large.count(nft));
+                if count > 0 { Some((*nft, count)) } else { None }
+            })
+            .collect();
+        NftBag { counts }
+    }
+
+    // nfts in this bag which are left after taking away nfts in [other]
+    pub fn subtract(&self, other: &NftBag) -> NftBag {
+        let counts = self.counts
+            .iter()
+            .filter_map(|(nft, &count)| {
+                let count = count.saturating_sub(other.count(nft));
+                if count > 0 { Some((*nft, count)) } else { None }
+            })
+            .collect();
+        NftBag { counts }
+    }
+
+    // check if every nft in this bag is owned by [other] with at least the same count
+    pub fn is_subset(&self, other: &NftBag) -> bool {
+        self.counts.iter().all(|(nft, &count)| other.count(nft) >= count)
+    }
+
+    // flatten into nft list in ASC order of nft blake160
+    pub fn to_vec(&self) -> Vec<[u8; 20]> {
+        let mut nfts = self.counts
+            .iter()
+            .flat_map(|(&nft, &count)| vec![nft; count as usize])
+            .collect::<Vec<_>>();
+        nfts.sort_unstable();
+        nfts
+    }
+}
+
+impl FromIterator<[u8; 20]> for NftBag {
+    fn from_iter<T: IntoIterator<Item = [u8; 20]>>(nfts: T) -> Self {
+        let mut bag = NftBag::new();
+        nfts.into_iter().for_each(|nft| bag.insert(nft));
+        bag
+    }
+}
+
+impl From<&[[u8; 20]]> for NftBag {
+    fn from(nfts: &[[u8; 20]]) -> Self {
+        nfts.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ckb::transaction::helper::blake160_intersect;
+
+    #[test]
+    fn test_nft_bag() {
+        let bag1 = NftBag::from(&[[1u8; 20], [1u8; 20], [2u8; 20], [3u8; 20]][..]);
+        let bag2 = NftBag::from(&[[1u8; 20], [2u8; 20], [2u8; 20], [4u8; 20]][..]);
+        assert_eq!(bag1.len(), 4);
+        assert_eq!(bag1.count(&[1u8; 20]), 2);
+        assert_eq!(bag1.intersect(&bag2).to_vec(), vec![[1u8; 20], [2u8; 20]]);
+        assert_eq!(bag1.intersect(&bag2), bag2.intersect(&bag1));
+        assert_eq!(bag1.subtract(&bag2).to_vec(), vec![[1u8; 20], [3u8; 20]]);
+        assert!(bag1.intersect(&bag2).is_subset(&bag1));
+        assert!(!bag1.is_subset(&bag2));
+        assert!(NftBag::new().is_subset(&bag2));
+        assert_eq!(bag1.union(&bag2).len(), 8);
+        assert_eq!(bag1.union(&bag2).subtract(&bag2), bag1);
+        assert_eq!(NftBag::from_bytes(&[[1u8; 20], [2u8; 20]].concat()).to_vec(), vec![[1u8; 20], [2u8; 20]]);
+
+        let mut bag = bag1.clone();
+        assert!(bag.remove(&[3u8; 20]));
+        assert!(!bag.remove(&[3u8; 20]));
+        assert_eq!(bag.len(), 3);
+    }
+
+    #[test]
+    fn test_blake160_intersect() {
+        let mut nfts1 = vec![[1u8; 20], [2u8; 20], [1u8; 20], [3u8; 20]];
+        let mut nfts2 = vec![[3u8; 20], [1u8; 20], [4u8; 20], [1u8; 20], [1u8; 20]];
+        let inter_nfts = blake160_intersect(&mut nfts1, &mut nfts2);
+        assert_eq!(inter_nfts, vec![[1u8; 20], [1u8; 20], [3u8; 20]]);
+        assert_eq!(nfts1, vec![[2u8; 20]]);
+        assert_eq!(nfts2, vec![[4u8; 20], [1u8; 20]]);
+    }
+}
@@ -0,0 +1,343 @@
+use ckb_types::{
+    prelude::*, packed::{
+        OutPoint, CellOutput, Script
+    }
+};
+use std::collections::{
+    HashMap, HashSet
+};
+use super::bag::NftBag;
+
+// where one nft lives on chain, [index] is the position of the nft in cell data (in 20 bytes), [lock_script] is the
+// owner lock which could be any lock, and [type_script] is the nft type script which tells the composer who issued it
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NftLocation {
+    pub out_point:   OutPoint,
+    pub index:       usize,
+    pub lock_script: Script,
+    pub type_script: Script,
+    pub capacity:    u64
+}
+
+// one cell of the inventory, [nfts] are pairs of index in cell data and nft blake160 in ASC order of index
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NftCell {
+    pub out_point:   OutPoint,
+    pub lock_script: Script,
+    pub type_script: Script,
+    pub capacity:    u64,
+    pub nfts:        Vec<(usize, [u8; 20])>
+}
+
+impl NftCell {
+    pub fn locations(&self) -> Vec<([u8; 20], NftLocation)> {
+        self.nfts
+            .iter()
+            .map(|&(index, nft)| {
+                (nft, NftLocation {
+                    out_point:   self.out_point.clone(),
+                    index,
+                    lock_script: self.lock_script.clone(),
+                    type_script: self.type_script.clone(),
+                    capacity:    self.capacity
+                })
+            })
+            .collect()
+    }
+}
+
+// live nfts indexed by nft blake160, each of which has a location list since one nft may be owned many times, it works
+// as a multiset of nfts while keeping track of the cells holding them
+//
+// [cell_index] maps out_point to its position in [cells] and [placed] keeps (out_point, index) of inserted locations,
+// so that inserting and grouping nfts into cells don't scan the whole inventory
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NftInventory {
+    nfts:       HashMap<[u8; 20], Vec<NftLocation>>,
+    cells:      Vec<OutPoint>,
+    cell_index: HashMap<OutPoint, usize>,
+    placed:     HashSet<(OutPoint, usize)>
+}
+
+impl NftInventory {
+    pub fn new() -> NftInventory {
+        NftInventory::default()
+    }
+
+    // insert all nfts in nft cell [output] at [out_point] with [data], cells without type script are ignored
+    pub fn insert_cell(&mut self, out_point: OutPoint, output: &CellOutput, data: &[u8]) {
+        let type_script = match output.type_().to_opt() {
+            Some(script) => script,
+            None         => return
+        };
+        let lock_script = output.lock();
+        let capacity: u64 = output.capacity().unpack();
+        for (index, nft) in data.chunks_exact(20).enumerate() {
+            let mut blake160 = [0u8; 20];
+            blake160.copy_from_slice(nft);
+            self.insert(blake160, NftLocation {
+                out_point: out_point.clone(), index, lock_script: lock_script.clone(), type_script: type_script.clone(), capacity
+            });
+        }
+    }
+
+    // insert one [nft] at [location], the same location won't be inserted twice
+    pub fn insert(&mut self, nft: [u8; 20], location: NftLocation) {
+        if !self.placed.insert((location.out_point.clone(), location.index)) {
+            return
+        }
+        if !self.cell_index.contains_key(&location.out_point) {
+            self.cell_index.insert(location.out_point.clone(), self.cells.len());
+            self.cells.push(location.out_point.clone());
+        }
+        self.nfts.entry(nft).or_default().push(location);
+    }
+
+    // the number of owned [nft]
+    pub fn count(&self, nft: &[u8; 20]) -> u32 {
+        self.nfts.get(nft).map(|locations| locations.len() as u32).unwrap_or(0)
+    }
+
+    // the total number of owned nfts
+    pub fn len(&self) -> usize {
+        self.nfts.values().map(|locations| locations.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn locations(&self, nft: &[u8; 20]) -> &[NftLocation] {
+        self.nfts.get(nft).map(|locations| &locations[..]).unwrap_or(&[])
+    }
+
+    // the flattened nft counts, which is the same as rpc::get_live_nfts returns
+    pub fn counts(&self) -> HashMap<[u8; 20], u32> {
+        self.nfts
+            .iter()
+            .filter(|(_, locations)| !locations.is_empty())
+            .map(|(&nft, locations)| (nft, locations.len() as u32))
+            .collect()
+    }
+
+    // the cells holding nfts in insertion order
+    pub fn cells(&self) -> Vec<NftCell> {
+        let mut cells: Vec<Option<NftCell>> = vec![None; self.cells.len()];
+        for (nft, locations) in &self.nfts {
+            for location in locations {
+                if let Some(&i) = self.cell_index.get(&location.out_point) {
+                    cells[i]
+                        .get_or_insert_with(|| NftCell {
+                            out_point:   location.out_point.clone(),
+                            lock_script: location.lock_script.clone(),
+                            type_script: location.type_script.clone(),
+                            capacity:    location.capacity,
+                            nfts:        vec![]
+                        })
+                        .nfts
+                        .push((location.index, *nft));
+                }
+            }
+        }
+        cells
+            .into_iter()
+            .flatten()
+            .map(|mut cell| {
+                cell.nfts.sort_by_key(|(index, _)| *index);
+                cell
+            })
+            .collect()
+    }
+
+    // split nfts into inventories by their type scripts which identify the composers
+    pub fn by_composer(&self) -> HashMap<Script, NftInventory> {
+        let mut composers: HashMap<Script, NftInventory> = HashMap::new();
+        for cell in self.cells() {
+            let inventory = composers.entry(cell.type_script.clone()).or_default();
+            for (nft, location) in cell.locations() {
+                inventory.insert(nft, location);
+            }
+        }
+        composers
+    }
+
+    // all nfts from both inventories, the same locations are only counted once
+    pub fn union(&self, other: &NftInventory) -> NftInventory {
+        let mut inventory = self.clone();
+        for cell in other.cells() {
+            for (nft, location) in cell.locations() {
+                inventory.insert(nft, location);
+            }
+        }
+        inventory
+    }
+
+    // remove as many of each nft as [other] owns, the locations shared with [other] are removed first
+    pub fn difference(&self, other: &NftInventory) -> NftInventory {
+        let mut inventory = self.clone();
+        for (nft, locations) in inventory.nfts.iter_mut() {
+            let shared = other.locations(nft).iter().collect::<HashSet<_>>();
+            let mut remove = shared.len();
+            locations.retain(|location| {
+                if remove > 0 && shared.contains(location) {
+                    remove -= 1;
+                    false
+                } else {
+                    true
+                }
+            });
+            locations.truncate(locations.len().saturating_sub(remove));
+        }
+        inventory.nfts.retain(|_, locations| !locations.is_empty());
+        inventory.reindex();
+        inventory
+    }
+
+    // check if all [nfts] are owned, duplicate nfts in [nfts] require the same number of owned ones
+    pub fn contains_all(&self, nfts: &[[u8; 20]]) -> bool {
+        NftBag::from(nfts)
+            .counts()
+            .iter()
+            .all(|(nft, &count)| self.count(nft) >= count)
+    }
+
+    // the owned nfts as a multiset
+    pub fn bag(&self) -> NftBag {
+        self.nfts
+            .iter()
+            .flat_map(|(&nft, locations)| vec![nft; locations.len()])
+            .collect()
+    }
+
+    // rebuild [cells], [cell_index] and [placed] from the remaining locations, cells keep their insertion order
+    fn reindex(&mut self) {
+        self.placed = self.nfts
+            .values()
+            .flatten()
+            .map(|location| (location.out_point.clone(), location.index))
+            .collect();
+        let live_cells = self.placed
+            .iter()
+            .map(|(out_point, _)| out_point)
+            .collect::<HashSet<_>>();
+        let cells = self.cells
+            .iter()
+            .filter(|out_point| live_cells.contains(out_point))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.cell_index = cells
+            .iter()
+            .enumerate()
+            .map(|(i, out_point)| (out_point.clone(), i))
+            .collect();
+        self.cells = cells;
+    }
+
+    // pick the cells which cover all [nfts] in insertion order, returns the picked cells and the nfts which can't be
+    // covered
+    pub fn select_cells(&self, nfts: &[[u8; 20]]) -> (Vec<NftCell>, Vec<[u8; 20]>) {
+        let mut required = NftBag::from(nfts);
+        let mut selected = vec![];
+        for cell in self.cells() {
+            if required.is_empty() {
+                break
+            }
+            let mut intersected = false;
+            for (_, nft) in &cell.nfts {
+                intersected |= required.remove(nft);
+            }
+            if intersected {
+                selected.push(cell);
+            }
+        }
+        (selected, required.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ckb_types::{
+        bytes::Bytes, core::Capacity
+    };
+
+    fn nft_cell(inventory: &mut NftInventory, tx_hash: u8, composer: u8, nfts: Vec<u8>) -> OutPoint {
+        locked_nft_cell(inventory, Script::default(), tx_hash, composer, nfts)
+    }
+
+    fn locked_nft_cell(inventory: &mut NftInventory, lock: Script, tx_hash: u8, composer: u8, nfts: Vec<u8>) -> OutPoint {
+        let out_point = OutPoint::new([tx_hash; 32].pack(), 0);
+        let output = CellOutput::new_builder()
+            .lock(lock)
+            .type_(Some(Script::new_builder().args(Bytes::from(vec![composer; 32]).pack()).build()).pack())
+            .capacity(Capacity::bytes(100).unwrap().pack())
+            .build();
+        let data = nfts.iter().flat_map(|&nft| [nft; 20].to_vec()).collect::<Vec<_>>();
+        inventory.insert_cell(out_point.clone(), &output, &data);
+        out_point
+    }
+
+    #[test]
+    fn test_nft_inventory() {
+        let mut alice = NftInventory::new();
+        let cell1 = nft_cell(&mut alice, 1, 1, vec![1, 2, 2]);
+        let cell2 = nft_cell(&mut alice, 2, 2, vec![3, 1]);
+        assert_eq!(alice.len(), 5);
+        assert_eq!(alice.count(&[2u8; 20]), 2);
+        assert_eq!(alice.counts().get(&[1u8; 20]), Some(&2));
+        assert_eq!(alice.locations(&[3u8; 20])[0].out_point, cell2);
+        assert_eq!(alice.locations(&[3u8; 20])[0].capacity, Capacity::bytes(100).unwrap().as_u64());
+        assert_eq!(alice.by_composer().len(), 2);
+        nft_cell(&mut alice, 1, 1, vec![1, 2, 2]);
+        assert_eq!(alice.len(), 5);
+        assert_eq!(alice.cells().len(), 2);
+
+        // multiset operations
+        assert!(alice.contains_all(&[[1u8; 20], [1u8; 20], [2u8; 20]]));
+        assert!(!alice.contains_all(&[[3u8; 20], [3u8; 20]]));
+        let mut bob = NftInventory::new();
+        nft_cell(&mut bob, 3, 1, vec![2, 4]);
+        let union = alice.union(&bob);
+        assert_eq!(union.len(), 7);
+        assert_eq!(union.union(&bob), union);
+        assert_eq!(union.difference(&bob), alice);
+        let difference = alice.difference(&bob);
+        assert_eq!(difference.len(), 4);
+        assert_eq!(difference.count(&[2u8; 20]), 1);
+        assert!(difference.difference(&alice).is_empty());
+        assert!(difference.difference(&alice).cells().is_empty());
+
+        // cells selection
+        let (selected, uncovered) = alice.select_cells(&[[1u8; 20], [3u8; 20]]);
+        assert_eq!(selected.len(), 2);
+        assert_eq!(selected[0].out_point, cell1);
+        assert_eq!(selected[0].nfts, vec![(0, [1u8; 20]), (1, [2u8; 20]), (2, [2u8; 20])]);
+        assert!(uncovered.is_empty());
+        let (selected, uncovered) = alice.select_cells(&[[4u8; 20]]);
+        assert!(selected.is_empty());
+        assert_eq!(uncovered, vec![[4u8; 20]]);
+    }
+
+    #[test]
+    fn test_multi_lock_inventory() {
+        // one owner holds nfts under sighash and multisig locks, which are searched apart and united
+        let sighash_lock = Script::new_builder().args(Bytes::from(vec![1u8; 20]).pack()).build();
+        let multisig_lock = Script::new_builder().code_hash([2u8; 32].pack()).args(Bytes::from(vec![3u8; 20]).pack()).build();
+        let mut sighash = NftInventory::new();
+        let sighash_cell = locked_nft_cell(&mut sighash, sighash_lock.clone(), 1, 1, vec![1, 2]);
+        let mut multisig = NftInventory::new();
+        let multisig_cell = locked_nft_cell(&mut multisig, multisig_lock.clone(), 2, 1, vec![2, 3]);
+        let owned = sighash.union(&multisig);
+        assert_eq!(owned.len(), 4);
+        assert_eq!(owned.count(&[2u8; 20]), 2);
+        assert_eq!(owned.union(&multisig), owned);
+        assert_eq!(owned.difference(&multisig), sighash);
+
+        // cells keep their own locks so that the caller knows which groups to sign
+        let (selected, uncovered) = owned.select_cells(&[[1u8; 20], [3u8; 20]]);
+        assert!(uncovered.is_empty());
+        assert_eq!(selected.len(), 2);
+        assert_eq!((selected[0].out_point.clone(), selected[0].lock_script.clone()), (sighash_cell, sighash_lock));
+        assert_eq!((selected[1].out_point.clone(), selected[1].lock_script.clone()), (multisig_cell, multisig_lock));
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{
+    Deserialize, Serialize
+};
+use std::collections::{
+    BTreeMap, HashMap
+};
+use std::convert::TryInto;
+use crate::error::{
+    Result, KabletopError
+};
+
+// card information of one nft, [attributes] are game-defined stats (e.g. cost, attack or rarity) which are kept as raw
+// json values since every game has its own card design
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct NftMetadata {
+    pub name:        String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image:       Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes:  BTreeMap<String, serde_json::Value>
+}
+
+// json format of the metadata manifest published by composer, which is either an off-chain file or the data of a
+// metadata cell, nft ids are blake160 in hex
+//
+// { "nfts": { "<blake160>": { "name": "...", "description": "...", "image": "...", "attributes": { ... } }, ... } }
+#[derive(Deserialize, Serialize)]
+struct Manifest {
+    nfts: BTreeMap<String, NftMetadata>
+}
+
+// nft metadata keyed by nft blake160
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NftRegistry {
+    metadata: HashMap<[u8; 20], NftMetadata>
+}
+
+impl NftRegistry {
+    pub fn new() -> NftRegistry {
+        NftRegistry::default()
+    }
+
+    // parse registry from json [manifest]
+    pub fn from_json(manifest: &[u8]) -> Result<NftRegistry> {
+        let manifest: Manifest = serde_json::from_slice(manifest)?;
+        let mut registry = NftRegistry::new();
+        for (id, metadata) in manifest.nfts {
+            let nft: [u8; 20] = hex::decode(id.trim_start_matches("0x"))?
+                .try_into()
+                .map_err(|_| KabletopError::NftMetadata(format!("nft id {} isn't blake160", id)))?;
+            if registry.metadata.insert(nft, metadata).is_some() {
+                return Err(KabletopError::NftMetadata(format!("duplicate nft id {}", id)));
+            }
+        }
+        Ok(registry)
+    }
+
+    // serialize registry into json manifest in ASC order of nft id
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        let nfts = self.metadata
+            .iter()
+            .map(|(nft, metadata)| (hex::encode(nft), metadata.clone()))
+            .collect();
+        Ok(serde_json::to_vec(&Manifest { nfts })?)
+    }
+
+    pub fn insert(&mut self, nft: [u8; 20], metadata: NftMetadata) {
+        self.metadata.insert(nft, metadata);
+    }
+
+    pub fn get(&self, nft: &[u8; 20]) -> Option<&NftMetadata> {
+        self.metadata.get(nft)
+    }
+
+    pub fn len(&self) -> usize {
+        self.metadata.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metadata.is_empty()
+    }
+
+    // check registry against the [nfts] in composer config tables, every nft in registry must be composed
+    pub fn check(&self, nfts: &[[u8; 20]]) -> Result<()> {
+        let mut unknown = self.metadata
+            .keys()
+            .filter(|nft| !nfts.contains(nft))
+            .map(hex::encode)
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            unknown.sort();
+            return Err(KabletopError::NftMetadata(format!("nfts not composed: {}", unknown.join(", "))));
+        }
+        Ok(())
+    }
+
+    // the [nfts] without metadata, which could be shown by blake160 only
+    pub fn missing(&self, nfts: &[[u8; 20]]) -> Vec<[u8; 20]> {
+        nfts.iter()
+            .filter(|nft| !self.metadata.contains_key(*nft))
+            .cloned()
+            .collect()
+    }
+
+    // pair each of [nfts] with its metadata, e.g. to show an inventory or a deck
+    pub fn describe<'a>(&'a self, nfts: &[[u8; 20]]) -> Vec<([u8; 20], Option<&'a NftMetadata>)> {
+        nfts.iter().map(|nft| (*nft, self.get(nft))).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nft_registry() {
+        let manifest = format!(
+            r#"{{ "nfts": {{ "{}": {{ "name": "Knight", "attributes": {{ "attack": 3 }} }}, "0x{}": {{ "name": "Dragon" }} }} }}"#,
+            hex::encode([1u8; 20]), hex::encode([2u8; 20])
+        );
+        let registry = NftRegistry::from_json(manifest.as_bytes()).expect("from_json");
+        assert_eq!(registry.len(), 2);
+        let knight = registry.get(&[1u8; 20]).expect("knight");
+        assert_eq!(knight.name, "Knight");
+        assert_eq!(knight.attributes.get("attack"), Some(&serde_json::json!(3)));
+        assert_eq!(NftRegistry::from_json(&registry.to_json().expect("to_json")).expect("reparse"), registry);
+
+        // check against composed nfts
+        registry.check(&[[1u8; 20], [2u8; 20], [3u8; 20]]).expect("check");
+        assert_eq!(registry.check(&[[1u8; 20]]).unwrap_err().code(), 311);
+        assert_eq!(registry.missing(&[[1u8; 20], [3u8; 20]]), vec![[3u8; 20]]);
+        assert_eq!(registry.describe(&[[3u8; 20]]), vec![([3u8; 20], None)]);
+
+        // bad nft ids
+        assert!(NftRegistry::from_json(br#"{ "nfts": { "0102": { "name": "Short" } } }"#).is_err());
+        assert!(NftRegistry::from_json(br#"{ "nfts": { "zz": { "name": "Bad" } } }"#).is_err());
+    }
+}
@@ -1,7 +1,15 @@
 mod utils;
 mod tx_extends;
 mod types;
+mod inventory;
+mod bag;
+mod offer;
+mod metadata;
 
 pub use utils::*;
 pub use tx_extends::*;
-pub use types::*;
\ No newline at end of file
+pub use types::*;
+pub use inventory::*;
+pub use bag::*;
+pub use offer::*;
+pub use metadata::*;
\ No newline at end of file
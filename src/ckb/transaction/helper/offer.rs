@@ -0,0 +1,177 @@
+use ckb_types::{
+    prelude::*, bytes::Bytes, core::Capacity, packed::{
+        OutPoint, CellOutput, Script
+    }
+};
+use crate::error::Result;
+use std::convert::TryInto;
+
+// the setup byte in sale lock args of an order which is paid in ckb
+pub const SALE_SETUP_CKB: u8 = 0;
+
+// get sale lock args which is seller_lock (molecule Script) | setup (uint8) | total_value (uint128 in little endian),
+// [total_value] is the capacity seller receives in one cell when the offer is filled, and the seller lock goes first so
+// that the indexer could search offers of one seller by args prefix
+pub fn sale_args(seller_lock: &Script, total_value: u64) -> Vec<u8> {
+    [seller_lock.as_slice(), &[SALE_SETUP_CKB][..], &(total_value as u128).to_le_bytes()[..]].concat()
+}
+
+// get seller lock and total value from sale lock [args] which is made from sale_args, orders paid in other assets are
+// rejected
+pub fn parse_sale_args(args: &[u8]) -> Option<(Script, u64)> {
+    if args.len() < 17 {
+        return None
+    }
+    let (seller_lock, rest) = args.split_at(args.len() - 17);
+    if rest[0] != SALE_SETUP_CKB {
+        return None
+    }
+    let seller_lock = Script::from_slice(seller_lock).ok()?;
+    let total_value = u128::from_le_bytes(rest[1..].try_into().ok()?).try_into().ok()?;
+    Some((seller_lock, total_value))
+}
+
+// one open nft sale offer, which is an nft cell locked by sale lock at [out_point], [nfts] are for sale as a whole
+// and go to the buyer who pays [price_ckb] to [seller_lock], along with the capacity of offer cell
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NftOffer {
+    pub out_point:   OutPoint,
+    pub output:      CellOutput,
+    pub nfts:        Vec<[u8; 20]>,
+    pub seller_lock: Script,
+    pub price_ckb:   u64
+}
+
+impl NftOffer {
+    // parse offer from cell [output] at [out_point] with [data], returns None if the cell isn't an nft cell or the lock
+    // args aren't made from sale_args
+    pub fn from_cell(out_point: OutPoint, output: CellOutput, data: &[u8]) -> Option<NftOffer> {
+        if output.type_().is_none() || data.is_empty() || !data.chunks_exact(20).remainder().is_empty() {
+            return None
+        }
+        let (seller_lock, total_value) = parse_sale_args(&output.lock().args().raw_data())?;
+        let capacity: u64 = output.capacity().unpack();
+        let price_ckb = total_value.checked_sub(capacity)?;
+        let nfts = data
+            .chunks_exact(20)
+            .map(|nft| nft.try_into().unwrap())
+            .collect();
+        Some(NftOffer { out_point, output, nfts, seller_lock, price_ckb })
+    }
+
+    pub fn type_script(&self) -> Script {
+        self.output.type_().to_opt().expect("nft offer type")
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.output.capacity().unpack()
+    }
+
+    // the capacity seller receives when the offer is filled, which is price_ckb plus the capacity of offer cell
+    pub fn total_value(&self) -> u64 {
+        self.price_ckb + self.capacity()
+    }
+
+    // the outputs which fill this offer when it's the first input, the payment cell to seller_lock must stay at index 0
+    // to match the offer input and gives back the capacity of offer cell, and the nfts go to [buyer_lock] in a cell of
+    // exact capacity which is paid by buyer
+    pub fn fill_outputs(&self, buyer_lock: Script) -> Result<Vec<(CellOutput, Bytes)>> {
+        let payment_output = CellOutput::new_builder()
+            .lock(self.seller_lock.clone())
+            .capacity(self.total_value().pack())
+            .build();
+        let nft_data = Bytes::from(self.nfts.concat());
+        let nft_output = CellOutput::new_builder()
+            .lock(buyer_lock)
+            .type_(Some(self.type_script()).pack())
+            .build_exact_capacity(Capacity::bytes(nft_data.len())?)?;
+        Ok(vec![(payment_output, Bytes::new()), (nft_output, nft_data)])
+    }
+
+    // the output which takes the nfts back to seller_lock when the offer is cancelled
+    pub fn cancel_output(&self) -> (CellOutput, Bytes) {
+        let output = CellOutput::new_builder()
+            .lock(self.seller_lock.clone())
+            .type_(Some(self.type_script()).pack())
+            .capacity(self.capacity().pack())
+            .build();
+        (output, Bytes::from(self.nfts.concat()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ckb_types::{
+        bytes::Bytes, core::Capacity
+    };
+
+    fn lock(byte: u8) -> Script {
+        Script::new_builder().args(Bytes::from(vec![byte; 20]).pack()).build()
+    }
+
+    #[test]
+    fn test_nft_offer() {
+        let total_value = Capacity::bytes(700).unwrap().as_u64();
+        let args = sale_args(&lock(1), total_value);
+        assert_eq!(parse_sale_args(&args), Some((lock(1), total_value)));
+        assert_eq!(parse_sale_args(&args[..args.len() - 16]), None);
+        assert_eq!(parse_sale_args(&args[1..]), None);
+        let mut other_setup = args.clone();
+        other_setup[args.len() - 17] = 1;
+        assert_eq!(parse_sale_args(&other_setup), None);
+
+        let output = CellOutput::new_builder()
+            .lock(Script::new_builder().args(Bytes::from(args).pack()).build())
+            .type_(Some(Script::default()).pack())
+            .capacity(Capacity::bytes(200).unwrap().pack())
+            .build();
+        let out_point = OutPoint::new([1u8; 32].pack(), 0);
+        let offer = NftOffer::from_cell(out_point.clone(), output.clone(), &[[2u8; 20], [3u8; 20]].concat()).expect("offer");
+        assert_eq!(offer.nfts, vec![[2u8; 20], [3u8; 20]]);
+        assert_eq!(offer.seller_lock, lock(1));
+        assert_eq!(offer.price_ckb, Capacity::bytes(500).unwrap().as_u64());
+        assert_eq!(offer.capacity(), Capacity::bytes(200).unwrap().as_u64());
+        assert_eq!(offer.total_value(), total_value);
+        assert!(NftOffer::from_cell(out_point.clone(), output.clone(), &[2u8; 19]).is_none());
+        let underpriced = output.clone().as_builder().capacity(Capacity::bytes(701).unwrap().pack()).build();
+        assert!(NftOffer::from_cell(out_point.clone(), underpriced, &[2u8; 20]).is_none());
+        let unlisted = output.as_builder().lock(Script::default()).build();
+        assert!(NftOffer::from_cell(out_point, unlisted, &[2u8; 20]).is_none());
+    }
+
+    #[test]
+    fn test_nft_offer_outputs() {
+        let (seller_lock, buyer_lock) = (lock(1), lock(4));
+        let price_ckb = Capacity::bytes(500).unwrap().as_u64();
+        let offer_capacity = Capacity::bytes(200).unwrap().as_u64();
+        let output = CellOutput::new_builder()
+            .lock(Script::new_builder().args(Bytes::from(sale_args(&seller_lock, price_ckb + offer_capacity)).pack()).build())
+            .type_(Some(Script::default()).pack())
+            .capacity(offer_capacity.pack())
+            .build();
+        let offer = NftOffer::from_cell(OutPoint::default(), output, &[[2u8; 20], [3u8; 20]].concat()).expect("offer");
+
+        // fill pays price_ckb and the capacity of offer cell back to seller at index 0, and the nfts go to buyer in a
+        // cell of exact capacity
+        let outputs = offer.fill_outputs(buyer_lock.clone()).expect("fill outputs");
+        assert_eq!(outputs.len(), 2);
+        let (payment, payment_data) = &outputs[0];
+        assert_eq!(payment.lock(), seller_lock);
+        assert!(payment.type_().is_none() && payment_data.is_empty());
+        assert_eq!(Unpack::<u64>::unpack(&payment.capacity()), price_ckb + offer_capacity);
+        let (nft, nft_data) = &outputs[1];
+        assert_eq!(nft.lock(), buyer_lock);
+        assert_eq!(nft.type_().to_opt(), Some(offer.type_script()));
+        assert_eq!(nft_data.to_vec(), offer.nfts.concat());
+        assert_eq!(nft.occupied_capacity(Capacity::bytes(nft_data.len()).unwrap()).unwrap().as_u64(), Unpack::<u64>::unpack(&nft.capacity()));
+        assert!(Unpack::<u64>::unpack(&nft.capacity()) < offer_capacity);
+
+        // cancel takes back the whole offer cell
+        let (nft, nft_data) = offer.cancel_output();
+        assert_eq!(nft.lock(), seller_lock);
+        assert_eq!(nft.type_().to_opt(), Some(offer.type_script()));
+        assert_eq!(nft_data.to_vec(), offer.nfts.concat());
+        assert_eq!(Unpack::<u64>::unpack(&nft.capacity()), offer_capacity);
+    }
+}
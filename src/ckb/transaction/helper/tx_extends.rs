@@ -4,16 +4,18 @@ use ckb_types::{
         DepType, TransactionView, Capacity, HeaderView
     },
     packed::{
-        OutPoint, CellDep, CellInput, CellOutput
+        OutPoint, CellDep, CellInput, CellOutput, Script
     }
 };
-use anyhow::{
-    Result, anyhow
-};
 use crate::{
-	config::VARS as _C,
+	error::{
+		Result, KabletopError
+	},
+	config::{
+		VARS as _C, types::ckb::Contract
+	},
 	ckb::{
-		transaction::genesis::GENESIS as _G,
+		transaction::genesis::genesis,
 		rpc::{
 			methods as rpc,
 			types::{
@@ -22,34 +24,38 @@ use crate::{
 		}
 	}
 };
-use super::utils::*;
+use super::{
+    utils::*, inventory::NftInventory, bag::NftBag
+};
 
 // add sighash_blake160 cell deps into [tx] which represents the basic lock script for ckb
-pub fn add_sighash_celldep(mut tx: TransactionView) -> TransactionView {
+pub fn add_sighash_celldep(mut tx: TransactionView) -> Result<TransactionView> {
+    let sighash_celldep = &genesis()?.sighash_celldep;
     let celldep = tx
         .cell_deps_iter()
-        .find(|dep| dep.out_point() == _G.sighash_celldep.out_point());
+        .find(|dep| dep.out_point() == sighash_celldep.out_point());
     if celldep.is_none() {
         tx = tx
             .as_advanced_builder()
-            .cell_dep(_G.sighash_celldep.clone())
+            .cell_dep(sighash_celldep.clone())
             .build();
     }
-    tx
+    Ok(tx)
 }
 
 // add multisig cell deps into [tx] which helps check signature from multi-parts
-pub fn add_multisig_celldep(mut tx: TransactionView) -> TransactionView {
+pub fn add_multisig_celldep(mut tx: TransactionView) -> Result<TransactionView> {
+    let multisig_celldep = &genesis()?.multisig_celldep;
     let celldep = tx
         .cell_deps_iter()
-        .find(|dep| dep.out_point() == _G.multisig_celldep.out_point());
+        .find(|dep| dep.out_point() == multisig_celldep.out_point());
     if celldep.is_none() {
         tx = tx
             .as_advanced_builder()
-            .cell_dep(_G.multisig_celldep.clone())
+            .cell_dep(multisig_celldep.clone())
             .build();
     }
-    tx
+    Ok(tx)
 }
 
 // add custom [outpoint] as a cell dep into [tx]
@@ -69,6 +75,27 @@ pub fn add_code_celldep(mut tx: TransactionView, outpoint: OutPoint) -> Transact
     tx
 }
 
+// search the live cell which is holding the binary of [contract], the cell is tracked by its type-id script if configured,
+// otherwise it's the first output of the configured deployment tx_hash
+pub async fn contract_outpoint(contract: &Contract) -> Result<OutPoint> {
+    if let Some(type_id) = &contract.type_id {
+        let search_key = SearchKey::new(type_id.clone().into(), ScriptType::Type);
+        let contract_cell = rpc::get_live_cells(search_key, 1, None).await?.objects;
+        if contract_cell.is_empty() {
+            return Err(KabletopError::ContractNotFound(format!("type-id {}", type_id.calc_script_hash())));
+        }
+        Ok(contract_cell[0].out_point.clone())
+    } else {
+        Ok(OutPoint::new(contract.tx_hash.clone(), 0))
+    }
+}
+
+// add the code cell of [contract] as a cell dep into [tx]
+pub async fn add_contract_celldep(tx: TransactionView, contract: &Contract) -> Result<TransactionView> {
+    let outpoint = contract_outpoint(contract).await?;
+    Ok(add_code_celldep(tx, outpoint))
+}
+
 // add [header] as a header dep into [tx]
 pub fn add_headerdep(mut tx: TransactionView, header: HeaderView) -> TransactionView {
     let headerdep = tx
@@ -101,7 +128,7 @@ pub async fn complete_tx_with_sighash_cells(tx: TransactionView, pubkey_hash: &[
     }
     let mut cursor = None;
     let mut tx_inputs = vec![];
-    let secp256k1_script = sighash_script(&pubkey_hash[..]);
+    let secp256k1_script = sighash_script(&pubkey_hash[..])?;
     while offered_capacity.as_u64() < required_capacity.as_u64() {
         let search_key = SearchKey::new(secp256k1_script.clone().into(), ScriptType::Lock);
         let live_cells = rpc::get_live_cells(search_key, 5, cursor).await?;
@@ -109,12 +136,17 @@ pub async fn complete_tx_with_sighash_cells(tx: TransactionView, pubkey_hash: &[
             .objects
             .iter()
             .filter(|cell| {
-				let is_contract = vec![&_C.nft.tx_hash, &_C.wallet.tx_hash, &_C.payment.tx_hash, &_C.kabletop.tx_hash, &_C.luacodes[0].tx_hash]
-					.iter()
-					.any(|&hash| hash == &cell.out_point.tx_hash());
+                // for every builder, configured code cells and any cell with data (e.g. code cells deployed in the same
+                // run which aren't in config yet) are never spent as capacity, since spending them destroys their data
+                let is_contract = vec![&_C.nft.tx_hash, &_C.wallet.tx_hash, &_C.payment.tx_hash, &_C.kabletop.tx_hash]
+                    .into_iter()
+                    .chain(_C.sale.iter().map(|sale| &sale.tx_hash))
+                    .chain(_C.luacodes.iter().map(|luacode| &luacode.tx_hash))
+                    .any(|hash| hash == &cell.out_point.tx_hash());
                 if is_contract
-					|| cell.output.type_().is_some()
-					|| offered_capacity.as_u64() >= required_capacity.as_u64() {
+                    || cell.output.type_().is_some()
+                    || !cell.output_data.is_empty()
+                    || offered_capacity.as_u64() >= required_capacity.as_u64() {
                     return false;
                 }
                 let input_capacity = Capacity::shannons(cell.output.capacity().unpack());
@@ -134,7 +166,10 @@ pub async fn complete_tx_with_sighash_cells(tx: TransactionView, pubkey_hash: &[
         cursor = Some(live_cells.last_cursor);
     }
     if offered_capacity.as_u64() < required_capacity.as_u64() {
-        return Err(anyhow!("required live secp256k1 cells are NOT enough"));
+        return Err(KabletopError::InsufficientCapacity {
+            required:  required_capacity.as_u64(),
+            available: offered_capacity.as_u64()
+        });
     }
     // prepare secp256k1 output cells to contain extra capacity
     let mut tx_outputs = vec![];
@@ -155,8 +190,7 @@ pub async fn complete_tx_with_sighash_cells(tx: TransactionView, pubkey_hash: &[
         .outputs(tx_outputs)
         .outputs_data(tx_outputs_data.pack())
         .build();
-    let tx = add_sighash_celldep(tx);
-    Ok(tx)
+    add_sighash_celldep(tx)
 }
 
 // collect and apply nft cells locked by [pubkey_hash] to [tx], the APPLY means put collected nft cells into input part
@@ -164,90 +198,124 @@ pub async fn complete_tx_with_sighash_cells(tx: TransactionView, pubkey_hash: &[
 //
 // all nft cells are collected by [nfts]
 pub async fn complete_tx_with_nft_cells(
-    tx: TransactionView, user_pkhash: &[u8; 20], composer_pkhash: &[u8; 20], mut required_nfts: Vec<[u8; 20]>, discard: bool
+    tx: TransactionView, user_pkhash: &[u8; 20], composer_pkhash: &[u8; 20], required_nfts: Vec<[u8; 20]>, discard: bool
 ) -> Result<TransactionView> {
-    let lock_script = sighash_script(&user_pkhash[..]);
+    let lock_script = sighash_script(&user_pkhash[..])?;
+    complete_tx_with_owned_nft_cells(tx, &[lock_script], composer_pkhash, required_nfts, discard).await
+}
+
+// the same as complete_tx_with_nft_cells but collects nft cells under any of [owner_locks] rather than only sighash
+// lock, the nfts left in picked cells go back to the lock of the cell they come from
+//
+// signer::sign only makes secp256k1 signatures for each lock group, so inputs under locks which need other witness
+// format should be signed by caller
+pub async fn complete_tx_with_owned_nft_cells(
+    tx: TransactionView, owner_locks: &[Script], composer_pkhash: &[u8; 20], required_nfts: Vec<[u8; 20]>, discard: bool
+) -> Result<TransactionView> {
+    if owner_locks.is_empty() {
+        return Err(KabletopError::InvalidTransaction(String::from("no owner lock to collect nft cells")));
+    }
     let type_script = {
         let wallet = wallet_script(composer_pkhash.to_vec());
         nft_script(wallet.calc_script_hash().raw_data().to_vec())
     };
+    let inventory = rpc::get_nft_inventory_covering(owner_locks.to_vec(), Some(type_script), &required_nfts, 10).await?;
+    complete_tx_with_nft_inventory(tx, &inventory, required_nfts, discard).await
+}
 
-    // search live nft cells using serach_key
-    let mut cursor = None;
+// complete [tx] with the nft cells picked from [inventory] which cover all [required_nfts], nfts in picked cells under
+// the same lock are merged into one output cell locked by it, and the required ones are left out if [discard], so
+// that no nft changes its lock unless it's required
+pub async fn complete_tx_with_nft_inventory(
+    tx: TransactionView, inventory: &NftInventory, required_nfts: Vec<[u8; 20]>, discard: bool
+) -> Result<TransactionView> {
+    let (selected_cells, uncovered_nfts) = inventory.select_cells(&required_nfts);
+    if !uncovered_nfts.is_empty() {
+        return Err(KabletopError::InsufficientNfts(uncovered_nfts.len()));
+    }
+
+    // collect picked nft cells which must be issued by the same composer, grouped by lock in the order of first seen
     let mut tx_inputs = vec![];
-    let mut tx_output_data = vec![];
-    let mut capacity = 0u64;
-    while !required_nfts.is_empty() {
-        let search_key = SearchKey::new(lock_script.clone().into(), ScriptType::Lock).filter(type_script.clone().into());
-        let live_cells = rpc::get_live_cells(search_key, 10, cursor).await?;
-        let mut inputs = live_cells.objects
+    let mut changes: Vec<(Script, u64, Vec<u8>)> = vec![];
+    let mut type_script = None;
+    let mut required_nfts = NftBag::from(&required_nfts[..]);
+    for cell in selected_cells {
+        if type_script.get_or_insert_with(|| cell.type_script.clone()) != &cell.type_script {
+            return Err(KabletopError::InvalidTransaction(String::from("required nfts are issued by different composers")));
+        }
+        let index = match changes.iter().position(|(lock_script, _, _)| lock_script == &cell.lock_script) {
+            Some(index) => index,
+            None => {
+                changes.push((cell.lock_script.clone(), 0, vec![]));
+                changes.len() - 1
+            }
+        };
+        let (_, capacity, output_data) = &mut changes[index];
+        cell.nfts
             .iter()
-            .filter(|cell| {
-                let mut data = cell.output_data.to_vec();
-                let mut nft = [0u8; 20];
-                let mut nfts = vec![];
-                let n = data.len() / 20;
-                for _ in 0..n {
-                    nft.copy_from_slice(&data[..20]);
-                    data = data[20..].to_vec();
-                    nfts.push(nft);
-                }
-				let mut intersected = false;
-				if blake160_intersect(&mut nfts, &mut required_nfts).len() > 0 {
-					// check whether destory shared nfts
-					if discard {
-						data = vec![];
-						nfts.iter().for_each(|nft| data.append(&mut nft.to_vec()));
-					} else {
-						data = cell.output_data.to_vec();
-					}
-					tx_output_data.append(&mut data);
-					intersected = true;
-				}
-				intersected
-            })
-            .map(|cell| {
-                let ckb: Capacity = cell.output.capacity().unpack();
-                capacity += ckb.as_u64();
-                CellInput::new_builder()
-                    .previous_output(cell.out_point.clone())
-                    .build()
-            })
-            .collect::<Vec<CellInput>>();
-        tx_inputs.append(&mut inputs);
-        if live_cells.last_cursor.is_empty() {
-            break;
-        } 
-        cursor = Some(live_cells.last_cursor);
-    }
-    if required_nfts.len() > 0 {
-        return Err(anyhow!("all owned nft cells cannot cover required nfts ({} left)", required_nfts.len()));
+            .filter(|(_, nft)| !discard || !required_nfts.remove(nft))
+            .for_each(|(_, nft)| output_data.extend_from_slice(nft));
+        *capacity += cell.capacity;
+        tx_inputs.push(CellInput::new_builder()
+            .previous_output(cell.out_point)
+            .build());
     }
 
-    // turn all searched nft cells into one output cell
-    let mut tx_output = CellOutput::new_builder()
-        .lock(lock_script)
-        .capacity(Capacity::shannons(capacity).pack())
-		.build();
-	
-	if tx_output_data.is_empty() {
-		tx_output = tx_output
-			.as_builder()
-			.build();
-	} else {
-		tx_output = tx_output
-			.as_builder()
-			.type_(Some(type_script).pack())
-			.build();
-	}
+    // turn picked nft cells under each lock into one output cell
+    let mut tx = tx
+        .as_advanced_builder()
+        .inputs(tx_inputs);
+    for (lock_script, capacity, output_data) in changes {
+        let mut tx_output = CellOutput::new_builder()
+            .lock(lock_script)
+            .capacity(Capacity::shannons(capacity).pack());
+        if !output_data.is_empty() {
+            tx_output = tx_output.type_(type_script.clone().pack());
+        }
+        tx = tx
+            .output(tx_output.build())
+            .output_data(Bytes::from(output_data).pack());
+    }
 
     // generate new transaction
-    let tx = tx
-        .as_advanced_builder()
-        .inputs(tx_inputs)
-        .output(tx_output)
-        .output_data(Bytes::from(tx_output_data).pack())
-        .build();
-    let tx = add_code_celldep(tx, OutPoint::new(_C.nft.tx_hash.clone(), 0));
+    let tx = add_contract_celldep(tx.build(), &_C.nft).await?;
     Ok(tx)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ckb_types::core::TransactionBuilder;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_complete_tx_with_nft_inventory() {
+        // one owner holds nfts under sighash and multisig locks, and the nfts left in each picked cell stay in its lock
+        let type_script = Script::new_builder().args(Bytes::from(vec![1u8; 32]).pack()).build();
+        let sighash_lock = Script::new_builder().args(Bytes::from(vec![2u8; 20]).pack()).build();
+        let multisig_lock = Script::new_builder().code_hash([3u8; 32].pack()).args(Bytes::from(vec![4u8; 20]).pack()).build();
+        let (nft1, nft2, nft3) = ([5u8; 20], [6u8; 20], [7u8; 20]);
+        let mut inventory = NftInventory::new();
+        for (tx_hash, lock, nfts) in vec![(1u8, &sighash_lock, [nft1, nft2]), (2u8, &multisig_lock, [nft2, nft3])] {
+            let output = CellOutput::new_builder()
+                .lock(lock.clone())
+                .type_(Some(type_script.clone()).pack())
+                .capacity(Capacity::bytes(200).unwrap().pack())
+                .build();
+            inventory.insert_cell(OutPoint::new([tx_hash; 32].pack(), 0), &output, &nfts.concat());
+        }
+        let tx = TransactionBuilder::default().build();
+        let tx = block_on(complete_tx_with_nft_inventory(tx, &inventory, vec![nft1, nft3], true)).expect("complete tx");
+        assert_eq!(tx.inputs().len(), 2);
+        assert_eq!(tx.outputs().len(), 2);
+        for lock in [sighash_lock, multisig_lock] {
+            let (output, data) = tx
+                .outputs_with_data_iter()
+                .find(|(output, _)| output.lock() == lock)
+                .expect("change cell of lock");
+            assert_eq!(output.type_().to_opt(), Some(type_script.clone()));
+            assert_eq!(Unpack::<u64>::unpack(&output.capacity()), Capacity::bytes(200).unwrap().as_u64());
+            assert_eq!(NftBag::from_bytes(&data), NftBag::from(&[nft2][..]));
+        }
+    }
+}
@@ -1,57 +1,216 @@
 use ckb_types::{
-    bytes::Bytes, core::Capacity, packed::Byte32
+    prelude::*, bytes::Bytes, core::{
+        Capacity, TransactionView
+    }, packed::{
+        Byte32, WitnessArgs
+    }
 };
 use std::{
-    mem::size_of, convert::TryInto
+    mem::size_of, collections::{
+        HashSet, HashMap
+    }, convert::{
+        TryInto, TryFrom
+    }
 };
 use ckb_hash::blake2b_256;
+use crate::error::NftConfigError;
+
+// the marker byte which takes the place of package_capacity in versioned config data, it's never a valid capacity
+// in legacy config data
+const VERSION_MARKER: u8 = 0;
+
+// versions of the config data format, Legacy is the original one whose rates are u8 cumulative thresholds and
+// consume one lottery byte per nft, and V2 has u16 cumulative thresholds and consumes two lottery bytes per nft
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NFTConfigVersion {
+    Legacy,
+    V2
+}
+
+impl NFTConfigVersion {
+    fn byte(&self) -> u8 {
+        match self {
+            NFTConfigVersion::Legacy => 1,
+            NFTConfigVersion::V2     => 2
+        }
+    }
+
+    // count of all possible lottery values
+    fn lottery_space(&self) -> u64 {
+        1 << (self.rate_size() * 8)
+    }
+
+    // bytes count of one rate and also one lottery
+    fn rate_size(&self) -> usize {
+        match self {
+            NFTConfigVersion::Legacy => size_of::<u8>(),
+            NFTConfigVersion::V2     => size_of::<u16>()
+        }
+    }
+}
 
 // composers use this data to represent their NFT creations
 pub struct NFTConfig {
-    package_price:    u64,                  // ckb price per nft package
-    package_capacity: u8,                   // nft count that one package could contain
-    nft_config_table: Vec<([u8; 20], u8)>  // array for nft blake160/rate pair (rate means the probability a nft revealed)
+    version:          NFTConfigVersion,      // format version of the config data
+    package_price:    u64,                   // ckb price per nft package
+    package_capacity: u8,                    // nft count that one package could contain
+    nft_config_table: Vec<([u8; 20], u16)>   // array for nft blake160/rate pair (rate means the probability a nft revealed)
 }
 
 impl NFTConfig {
+    // make a legacy config whose [nft_config_table] rates are cumulative thresholds in 256
+    pub fn try_new(
+        package_price: u64, package_capacity: u8, nft_config_table: Vec<([u8; 20], u8)>
+    ) -> Result<NFTConfig, NftConfigError> {
+        let nft_config_table = nft_config_table
+            .into_iter()
+            .map(|(nft, rate)| (nft, rate as u16))
+            .collect();
+        NFTConfig::try_with_version(NFTConfigVersion::Legacy, package_price, package_capacity, nft_config_table)
+    }
+
+    // make a v2 config whose [nft_config_table] rates are cumulative thresholds in 65536
+    pub fn try_new_v2(
+        package_price: u64, package_capacity: u8, nft_config_table: Vec<([u8; 20], u16)>
+    ) -> Result<NFTConfig, NftConfigError> {
+        NFTConfig::try_with_version(NFTConfigVersion::V2, package_price, package_capacity, nft_config_table)
+    }
+
+    // make a v2 config from non-cumulative [nft_weights], each nft is revealed at the probability of its weight divided
+    // by the total weight, which is rounded to lottery values but never to none for a nonzero weight
+    pub fn try_from_weights(
+        package_price: u64, package_capacity: u8, nft_weights: Vec<([u8; 20], u32)>
+    ) -> Result<NFTConfig, NftConfigError> {
+        let total_weight: u64 = nft_weights.iter().map(|&(_, weight)| weight as u64).sum();
+        if total_weight == 0 && !nft_weights.is_empty() {
+            return Err(NftConfigError::ZeroWeight);
+        }
+        let space = NFTConfigVersion::V2.lottery_space();
+        let nonzero_count = nft_weights.iter().filter(|&&(_, weight)| weight > 0).count();
+        if nonzero_count as u64 > space {
+            return Err(NftConfigError::TooManyWeights(nonzero_count));
+        }
+
+        // the last nft takes all lottery values left, so nfts of zero weight are moved ahead to get no value at all
+        let mut nft_weights = nft_weights;
+        nft_weights.sort_by_key(|&(_, weight)| weight > 0);
+
+        // floor the cumulative rates, and then move one lottery value to each nonzero weight floored to none from the
+        // nft holding the most values
+        let mut cumulative_weight = 0u64;
+        let mut last_rate = 0u64;
+        let mut counts = nft_weights
+            .iter()
+            .map(|&(_, weight)| {
+                cumulative_weight += weight as u64;
+                let rate = cumulative_weight * space / total_weight;
+                let count = rate - last_rate;
+                last_rate = rate;
+                count
+            })
+            .collect::<Vec<_>>();
+        for i in 0..counts.len() {
+            if nft_weights[i].1 > 0 && counts[i] == 0 {
+                let richest = (0..counts.len()).max_by_key(|&j| counts[j]).expect("nonempty counts");
+                counts[richest] -= 1;
+                counts[i] = 1;
+            }
+        }
+        let mut rate = 0u64;
+        let nft_config_table = nft_weights
+            .into_iter()
+            .zip(counts)
+            .map(|((nft, _), count)| {
+                rate += count;
+                (nft, rate.min(space - 1) as u16)
+            })
+            .collect();
+        NFTConfig::try_new_v2(package_price, package_capacity, nft_config_table)
+    }
+
+    // the same as try_new but panics on bad params
     pub fn new(
         package_price: u64, package_capacity: u8, nft_config_table: Vec<([u8; 20], u8)>
     ) -> NFTConfig {
+        NFTConfig::try_new(package_price, package_capacity, nft_config_table).expect("bad nft config params")
+    }
+
+    // the same as try_new_v2 but panics on bad params
+    pub fn new_v2(
+        package_price: u64, package_capacity: u8, nft_config_table: Vec<([u8; 20], u16)>
+    ) -> NFTConfig {
+        NFTConfig::try_new_v2(package_price, package_capacity, nft_config_table).expect("bad nft config params")
+    }
+
+    // the same as try_from_weights but panics on bad params
+    pub fn from_weights(
+        package_price: u64, package_capacity: u8, nft_weights: Vec<([u8; 20], u32)>
+    ) -> NFTConfig {
+        NFTConfig::try_from_weights(package_price, package_capacity, nft_weights).expect("bad nft config params")
+    }
+
+    fn try_with_version(
+        version: NFTConfigVersion, package_price: u64, package_capacity: u8, nft_config_table: Vec<([u8; 20], u16)>
+    ) -> Result<NFTConfig, NftConfigError> {
         // limit the basic params
-        if package_price == 0
-            || package_capacity == 0 
-            || nft_config_table.is_empty() {
-            panic!("bad nft config params");
-        }
-        // rates in nft_config_table should be ASC order
-        let mut last_rate = 0u8;
-        nft_config_table.iter().for_each(|&(_, rate)| {
+        if package_price == 0 || package_capacity == 0 {
+            return Err(NftConfigError::InvalidPackage);
+        }
+        // the price of a whole purchase (at most 255 packages) must fit in u64
+        if package_price.checked_mul(u8::MAX as u64).is_none() {
+            return Err(NftConfigError::PriceOverflow(package_price));
+        }
+        if nft_config_table.is_empty() {
+            return Err(NftConfigError::EmptyTable);
+        }
+        // rates in nft_config_table should be ASC order and nfts should be unique
+        let mut last_rate = 0u16;
+        let mut nfts = HashSet::new();
+        for (i, &(nft, rate)) in nft_config_table.iter().enumerate() {
             if last_rate > rate {
-                panic!("bad nft config rate param");
+                return Err(NftConfigError::UnsortedRates(i));
+            }
+            if !nfts.insert(nft) {
+                return Err(NftConfigError::DuplicateNft(nft));
             }
             last_rate = rate;
-        });
-        NFTConfig {
-            package_price, package_capacity, nft_config_table
         }
+        Ok(NFTConfig {
+            version, package_price, package_capacity, nft_config_table
+        })
+    }
+
+    pub fn package_capacity(&self) -> u8 {
+        self.package_capacity
     }
 
-    // get the total ckb price of [package_count] packages
-    pub fn buy_package(&self, package_count: u64) -> Capacity {
-        Capacity::shannons(self.package_price * package_count)
+    pub fn version(&self) -> NFTConfigVersion {
+        self.version
     }
 
-    // reveal [package_count] nft packages with the random seed which made from the mix of [transaction_root] and [uncles_hash]
+    // get the total ckb price of [package_count] packages, which never overflows since package_price is limited when
+    // constructing or parsing
+    pub fn buy_package(&self, package_count: u8) -> Capacity {
+        Capacity::shannons(self.package_price * package_count as u64)
+    }
+
+    // reveal [package_count] nft packages with lotteries drawn from [header_hash] (or the seed from reveal_seed), which
+    // are extended by chaining blake2b_256 when used up, each nft consumes one lottery byte in legacy format or two bytes
+    // (u16 in little endian) in v2 format, and goes to the first nft whose rate is greater than its lottery
     pub fn rip_package(&self, header_hash: Byte32, package_count: u8) -> Bytes {
         let mut lotteries = header_hash.raw_data().to_vec();
         let mut collection: Vec<u8> = vec![];
         let nft_count = package_count as usize * self.package_capacity as usize;
+        let lottery_size = self.version.rate_size();
         for i in 0..nft_count {
-            if i >= lotteries.len() {
+            while (i + 1) * lottery_size > lotteries.len() {
                 let next_hash = blake2b_256(lotteries.clone());
                 lotteries.append(&mut next_hash.to_vec());
             }
-            let lottery = lotteries[i];
+            let lottery = match self.version {
+                NFTConfigVersion::Legacy => lotteries[i] as u16,
+                NFTConfigVersion::V2     => u16::from_le_bytes([lotteries[i * 2], lotteries[i * 2 + 1]])
+            };
             let mut expect_nft: Option<[u8; 20]> = None;
             for &(nft, rate) in self.nft_config_table.iter() {
                 if lottery < rate {
@@ -69,33 +228,157 @@ impl NFTConfig {
         Bytes::from(collection)
     }
 
+    // get the exact drop chance of each nft in one reveal, which is the share of lottery values below its rate but not
+    // below the previous rate, and the last nft also takes all lottery values not below its rate as rip_package does
+    pub fn probabilities(&self) -> Vec<([u8; 20], f64)> {
+        let space = self.version.lottery_space();
+        let mut last_rate = 0u64;
+        let mut probabilities = self.nft_config_table
+            .iter()
+            .map(|&(nft, rate)| {
+                let count = (rate as u64).saturating_sub(last_rate);
+                last_rate = last_rate.max(rate as u64);
+                (nft, count)
+            })
+            .collect::<Vec<_>>();
+        if let Some((_, count)) = probabilities.last_mut() {
+            *count += space - last_rate;
+        }
+        probabilities
+            .into_iter()
+            .map(|(nft, count)| (nft, count as f64 / space as f64))
+            .collect()
+    }
+
+    // reveal [packages] packages one by one with header hashes derived from [seed], and count the revealed nfts, which
+    // helps checking the config before composing it on chain
+    pub fn simulate(&self, packages: u32, seed: u64) -> HashMap<[u8; 20], u64> {
+        let mut collection = HashMap::new();
+        for i in 0..packages {
+            let mut header_seed = seed.to_le_bytes().to_vec();
+            header_seed.append(&mut i.to_le_bytes().to_vec());
+            let header_hash = Byte32::new(blake2b_256(header_seed));
+            let nfts = self.rip_package(header_hash, 1);
+            for nft in nfts.chunks(20) {
+                *collection.entry(nft.try_into().unwrap()).or_insert(0) += 1;
+            }
+        }
+        collection
+    }
+
+    // get all nfts in this config
+    pub fn nfts(&self) -> Vec<[u8; 20]> {
+        self.nft_config_table.iter().map(|&(nft, _)| nft).collect()
+    }
+
     pub fn to_ckb_bytes(&self) -> Bytes {
         let mut bytes = vec![];
         bytes.append(&mut self.package_price.to_le_bytes().to_vec());
+        if self.version != NFTConfigVersion::Legacy {
+            bytes.push(VERSION_MARKER);
+            bytes.push(self.version.byte());
+        }
         bytes.append(&mut self.package_capacity.to_le_bytes().to_vec());
         for &(nft, rate) in self.nft_config_table.iter() {
             bytes.append(&mut nft.to_vec());
-            bytes.append(&mut rate.to_le_bytes().to_vec());
+            match self.version {
+                NFTConfigVersion::Legacy => bytes.push(rate as u8),
+                NFTConfigVersion::V2     => bytes.append(&mut rate.to_le_bytes().to_vec())
+            }
         }
         Bytes::from(bytes)
     }
 }
 
-impl From<Bytes> for NFTConfig {
-    fn from(ckb_bytes: Bytes) -> Self {
-        let mut stream = StreamFetcher{ index: 0, stream: &ckb_bytes.to_vec() };
+impl TryFrom<Bytes> for NFTConfig {
+    type Error = NftConfigError;
+
+    fn try_from(ckb_bytes: Bytes) -> Result<Self, Self::Error> {
+        let bytes = ckb_bytes.to_vec();
+        let mut stream = StreamFetcher{ index: 0, stream: &bytes };
+        if stream.count(size_of::<u64>() + size_of::<u8>()) == 0 {
+            return Err(NftConfigError::TruncatedHeader(bytes.len()));
+        }
         let package_price    = stream.get_u64();
-        let package_capacity = stream.get_u8();
+        let mut package_capacity = stream.get_u8();
+        let mut version = NFTConfigVersion::Legacy;
+        if package_capacity == VERSION_MARKER {
+            if stream.count(size_of::<u8>() * 2) == 0 {
+                return Err(NftConfigError::TruncatedHeader(bytes.len()));
+            }
+            version = match stream.get_u8() {
+                2 => NFTConfigVersion::V2,
+                v => return Err(NftConfigError::UnsupportedVersion(v))
+            };
+            package_capacity = stream.get_u8();
+        }
+        let item_size = size_of::<[u8; 20]>() + version.rate_size();
+        let trailing = stream.remain() % item_size;
+        if trailing > 0 {
+            return Err(NftConfigError::TrailingBytes(trailing));
+        }
         let mut nft_config_table = vec![];
-        let item_size = size_of::<[u8; 20]>() + size_of::<u8>();
         for _ in 0..stream.count(item_size) {
             let nft  = stream.get_blake160();
-            let rate = stream.get_u8();
+            let rate = match version {
+                NFTConfigVersion::Legacy => stream.get_u8() as u16,
+                NFTConfigVersion::V2     => stream.get_u16()
+            };
             nft_config_table.push((nft, rate));
         }
-        NFTConfig {
-            package_capacity, package_price, nft_config_table
+        NFTConfig::try_with_version(version, package_price, package_capacity, nft_config_table)
+    }
+}
+
+// the prefix which tells an airdrop record from other witnesses
+const AIRDROP_MAGIC: &[u8] = b"airdrop";
+
+// the on-chain record of one composer airdrop, which is placed into output_type of an extra witness (after witnesses
+// of inputs) of airdrop tx and signed by composer along with the tx
+//
+// record: "airdrop" | series(u8) | nft_count(u32) | memo(utf8)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AirdropRecord {
+    pub series:    u8,
+    pub nft_count: u32,
+    pub memo:      String
+}
+
+impl AirdropRecord {
+    pub fn to_bytes(&self) -> Bytes {
+        let mut bytes = AIRDROP_MAGIC.to_vec();
+        bytes.push(self.series);
+        bytes.append(&mut self.nft_count.to_le_bytes().to_vec());
+        bytes.append(&mut self.memo.as_bytes().to_vec());
+        Bytes::from(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<AirdropRecord> {
+        let record = bytes.strip_prefix(AIRDROP_MAGIC)?;
+        if record.len() < size_of::<u8>() + size_of::<u32>() {
+            return None
         }
+        Some(AirdropRecord {
+            series:    record[0],
+            nft_count: u32::from_le_bytes(record[1..5].try_into().ok()?),
+            memo:      String::from_utf8(record[5..].to_vec()).ok()?
+        })
+    }
+
+    pub fn to_witness(&self) -> WitnessArgs {
+        WitnessArgs::new_builder()
+            .output_type(Some(self.to_bytes()).pack())
+            .build()
+    }
+
+    // find the airdrop record in extra witnesses of [tx]
+    pub fn from_tx(tx: &TransactionView) -> Option<AirdropRecord> {
+        tx.witnesses()
+            .into_iter()
+            .skip(tx.inputs().len())
+            .filter_map(|witness| WitnessArgs::from_slice(&witness.raw_data()).ok())
+            .filter_map(|witness| witness.output_type().to_opt())
+            .find_map(|record| AirdropRecord::from_bytes(&record.raw_data()))
     }
 }
 
@@ -134,6 +417,177 @@ impl<'load> StreamFetcher<'load> {
     }
 
     fn count(&self, size: usize) -> usize {
-        (self.stream.len() - self.index) / size
+        self.remain() / size
+    }
+
+    fn remain(&self) -> usize {
+        self.stream.len() - self.index
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn nft(i: u8) -> [u8; 20] {
+        [i; 20]
+    }
+
+    // unique nfts paired with ASC sorted rates
+    fn nft_table<T: Arbitrary + Ord + Copy>() -> impl Strategy<Value = Vec<([u8; 20], T)>> {
+        prop::collection::btree_set(any::<[u8; 20]>(), 1..16)
+            .prop_flat_map(|nfts| {
+                let len = nfts.len();
+                (Just(nfts), prop::collection::vec(any::<T>(), len))
+            })
+            .prop_map(|(nfts, mut rates)| {
+                rates.sort();
+                nfts.into_iter().zip(rates).collect()
+            })
+    }
+
+    #[test]
+    fn test_legacy_config_bytes() {
+        let config = NFTConfig::new(100, 3, vec![(nft(1), 100), (nft(2), 255)]);
+        let bytes = config.to_ckb_bytes();
+        assert_eq!(bytes.len(), 8 + 1 + 2 * 21);
+        let config = NFTConfig::try_from(bytes.clone()).expect("parse config");
+        assert_eq!(config.version(), NFTConfigVersion::Legacy);
+        assert_eq!(config.to_ckb_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_v2_config_bytes() {
+        let config = NFTConfig::new_v2(100, 3, vec![(nft(1), 1000), (nft(2), 65535)]);
+        let bytes = config.to_ckb_bytes();
+        assert_eq!(bytes.len(), 8 + 3 + 2 * 22);
+        let config = NFTConfig::try_from(bytes.clone()).expect("parse config");
+        assert_eq!(config.version(), NFTConfigVersion::V2);
+        assert_eq!(config.nft_config_table, vec![(nft(1), 1000), (nft(2), 65535)]);
+        assert_eq!(config.to_ckb_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_from_weights() {
+        let config = NFTConfig::from_weights(100, 3, vec![(nft(1), 1), (nft(2), 1), (nft(3), 2)]);
+        assert_eq!(config.nft_config_table, vec![(nft(1), 16384), (nft(2), 32768), (nft(3), 65535)]);
+        let nfts = config.rip_package([7u8; 32].pack(), 30);
+        assert_eq!(nfts.len(), 30 * 3 * 20);
+
+        // a tiny weight between large ones still takes one lottery value, which is taken from the largest weight
+        let config = NFTConfig::from_weights(100, 1, vec![(nft(1), u32::MAX), (nft(2), 1), (nft(3), u32::MAX / 2)]);
+        assert_eq!(config.nft_config_table, vec![(nft(1), 43689), (nft(2), 43690), (nft(3), 65535)]);
+        assert_eq!(config.probabilities()[1], (nft(2), 1.0 / 65536.0));
+
+        // a zero weight takes no lottery value even if it's the last one
+        let config = NFTConfig::from_weights(100, 1, vec![(nft(1), 1), (nft(2), u32::MAX), (nft(3), 0)]);
+        assert_eq!(config.nft_config_table, vec![(nft(3), 0), (nft(1), 1), (nft(2), 65535)]);
+        assert_eq!(config.probabilities(), vec![(nft(3), 0.0), (nft(1), 1.0 / 65536.0), (nft(2), 65535.0 / 65536.0)]);
+    }
+
+    #[test]
+    fn test_probabilities() {
+        let config = NFTConfig::new(100, 1, vec![(nft(1), 64), (nft(2), 64), (nft(3), 192)]);
+        assert_eq!(config.probabilities(), vec![(nft(1), 0.25), (nft(2), 0.0), (nft(3), 0.75)]);
+        let config = NFTConfig::from_weights(100, 1, vec![(nft(1), 1), (nft(2), 3)]);
+        assert_eq!(config.probabilities(), vec![(nft(1), 0.25), (nft(2), 0.75)]);
+    }
+
+    #[test]
+    fn test_simulate() {
+        let config = NFTConfig::new(100, 2, vec![(nft(1), 64), (nft(2), 128)]);
+        let packages = 10000;
+        let collection = config.simulate(packages, 42);
+        assert_eq!(collection.values().sum::<u64>(), packages as u64 * 2);
+        for (nft, probability) in config.probabilities() {
+            let frequency = collection[&nft] as f64 / (packages as f64 * 2.0);
+            assert!((frequency - probability).abs() < 0.02, "frequency {} vs probability {}", frequency, probability);
+        }
+        assert_eq!(collection, config.simulate(packages, 42));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert_eq!(NFTConfig::try_new(0, 3, vec![(nft(1), 1)]).err(), Some(NftConfigError::InvalidPackage));
+        assert_eq!(NFTConfig::try_new(1, 3, vec![]).err(), Some(NftConfigError::EmptyTable));
+        let max_price = u64::MAX / 255;
+        assert_eq!(NFTConfig::new(max_price, 3, vec![(nft(1), 1)]).buy_package(255).as_u64(), max_price * 255);
+        assert_eq!(NFTConfig::try_new(max_price + 1, 3, vec![(nft(1), 1)]).err(), Some(NftConfigError::PriceOverflow(max_price + 1)));
+        assert_eq!(NFTConfig::try_new(1, 3, vec![(nft(1), 2), (nft(2), 1)]).err(), Some(NftConfigError::UnsortedRates(1)));
+        assert_eq!(NFTConfig::try_new(1, 3, vec![(nft(1), 1), (nft(1), 2)]).err(), Some(NftConfigError::DuplicateNft(nft(1))));
+        assert_eq!(NFTConfig::try_from_weights(1, 3, vec![(nft(1), 0)]).err(), Some(NftConfigError::ZeroWeight));
+        let equal_weights = |count: u32| {
+            (0..count)
+                .map(|i| {
+                    let mut nft = [0u8; 20];
+                    nft[..4].copy_from_slice(&i.to_le_bytes());
+                    (nft, 1)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(NFTConfig::try_from_weights(1, 3, equal_weights(65536)).is_ok());
+        assert_eq!(NFTConfig::try_from_weights(1, 3, equal_weights(65537)).err(), Some(NftConfigError::TooManyWeights(65537)));
+
+        let bytes = NFTConfig::new(1, 3, vec![(nft(1), 1)]).to_ckb_bytes();
+        assert_eq!(NFTConfig::try_from(bytes.slice(..5)).err(), Some(NftConfigError::TruncatedHeader(5)));
+        assert_eq!(NFTConfig::try_from(bytes.slice(..20)).err(), Some(NftConfigError::TrailingBytes(11)));
+        let mut data = bytes.to_vec();
+        data.push(0);
+        assert_eq!(NFTConfig::try_from(Bytes::from(data)).err(), Some(NftConfigError::TrailingBytes(1)));
+        let mut data = 1u64.to_le_bytes().to_vec();
+        data.extend_from_slice(&[VERSION_MARKER, 9, 3]);
+        assert_eq!(NFTConfig::try_from(Bytes::from(data)).err(), Some(NftConfigError::UnsupportedVersion(9)));
+
+        // a hostile config cell with a huge package price is rejected when parsing
+        let mut data = bytes.to_vec();
+        data[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(NFTConfig::try_from(Bytes::from(data)).err(), Some(NftConfigError::PriceOverflow(u64::MAX)));
+    }
+
+    #[test]
+    fn test_airdrop_record() {
+        let record = AirdropRecord { series: 1, nft_count: 42, memo: String::from("season 1 launch") };
+        assert_eq!(AirdropRecord::from_bytes(&record.to_bytes()), Some(record.clone()));
+        assert_eq!(AirdropRecord::from_bytes(&record.to_bytes()[..10]), None);
+        assert_eq!(AirdropRecord::from_bytes(b"airdrap\x01\x00\x00\x00\x00"), None);
+
+        // only extra witnesses are searched
+        let tx = TransactionView::new_advanced_builder()
+            .input(Default::default())
+            .witness(record.to_witness().as_bytes().pack())
+            .build();
+        assert_eq!(AirdropRecord::from_tx(&tx), None);
+        let tx = tx
+            .as_advanced_builder()
+            .witness(record.to_witness().as_bytes().pack())
+            .build();
+        assert_eq!(AirdropRecord::from_tx(&tx), Some(record));
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_legacy_roundtrip(price in 1u64..=u64::MAX / 255, capacity in 1u8.., table in nft_table::<u8>()) {
+            let bytes = NFTConfig::try_new(price, capacity, table).expect("valid config").to_ckb_bytes();
+            let config = NFTConfig::try_from(bytes.clone()).expect("parse config");
+            prop_assert_eq!(config.version(), NFTConfigVersion::Legacy);
+            prop_assert_eq!(config.to_ckb_bytes(), bytes);
+        }
+
+        #[test]
+        fn proptest_v2_roundtrip(price in 1u64..=u64::MAX / 255, capacity in 1u8.., table in nft_table::<u16>()) {
+            let bytes = NFTConfig::try_new_v2(price, capacity, table).expect("valid config").to_ckb_bytes();
+            let config = NFTConfig::try_from(bytes.clone()).expect("parse config");
+            prop_assert_eq!(config.version(), NFTConfigVersion::V2);
+            prop_assert_eq!(config.to_ckb_bytes(), bytes);
+        }
+
+        #[test]
+        fn proptest_parse_arbitrary_bytes(data in prop::collection::vec(any::<u8>(), 0..128)) {
+            // parsing never panics, and anything parsed encodes back to the same bytes
+            if let Ok(config) = NFTConfig::try_from(Bytes::from(data.clone())) {
+                prop_assert_eq!(config.to_ckb_bytes().to_vec(), data);
+            }
+        }
     }
 }
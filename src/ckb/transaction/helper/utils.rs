@@ -8,21 +8,31 @@ use ckb_types::{
     prelude::*, bytes::Bytes, core::{
         Capacity, ScriptHashType,
     }, packed::{
-        OutPoint, Script, CellOutput
+        OutPoint, Script, CellOutput, CellInput, Byte32
     }
 };
-use anyhow::{
-    Result, anyhow
-};
 use std::{
     str::FromStr, convert::TryInto
 };
+use super::bag::NftBag;
 use crate::{
-    config::VARS as _C, ckb::{
-        transaction::genesis::GENESIS as _G, rpc::methods as rpc,
+    error::{
+        Result, KabletopError
+    }, config::{
+        VARS as _C, types::ckb::{
+            self as conf, Contract
+        }
+    }, ckb::{
+        transaction::genesis::genesis, rpc::methods as rpc,
     }
 };
 
+// the well-known code_hash of ckb built-in type-id script, which is "TYPE_ID" in right-aligned hex
+pub const TYPE_ID_CODE_HASH: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0x54, 0x59, 0x50, 0x45, 0x5f, 0x49, 0x44
+];
+
 // turn a hex format of blake2b_256 [hash] into [u8; 32] format
 pub fn blake256_to_byte32(hash: &str) -> Result<[u8; 32]> {
     Ok(hex::decode(hash)?.try_into().expect("transport hex to byte32"))
@@ -41,21 +51,20 @@ pub fn blake160(data: &[u8]) -> [u8; 20] {
     buf
 }
 
-// find and remove the intersection from two nft collections
+// find and remove the intersection from two nft collections, the order of left nfts is kept
 pub fn blake160_intersect(nfts1: &mut Vec<[u8; 20]>, nfts2: &mut Vec<[u8; 20]>) -> Vec<[u8; 20]> {
+    let mut bag2 = nfts2.iter().cloned().collect::<NftBag>();
     let mut inter_nfts = vec![];
-    *nfts1 = nfts1
-        .iter()
-        .filter_map(|&nft1| {
-            let mut i2 = 0;
-            if nfts2.iter().enumerate().any(|(i, nft2)| { i2 = i; nft1[..] == nft2[..] }) {
-                inter_nfts.push(nfts2.remove(i2));
-                None
-            } else {
-                Some(nft1)
-            }
-        })
-        .collect();
+    nfts1.retain(|nft| {
+        if bag2.remove(nft) {
+            inter_nfts.push(*nft);
+            false
+        } else {
+            true
+        }
+    });
+    let mut inter_bag = inter_nfts.iter().cloned().collect::<NftBag>();
+    nfts2.retain(|nft| !inter_bag.remove(nft));
     inter_nfts
 }
 
@@ -67,7 +76,7 @@ pub fn outpoint_to_output(outpoint: OutPoint) -> Result<CellOutput> {
         .raw()
         .outputs()
         .get(out_index as usize)
-        .ok_or_else(|| anyhow!("index is out-of-bound in transaction outputs"))?;
+        .ok_or_else(|| KabletopError::CellNotFound(format!("{}", outpoint)))?;
     Ok(output)
 }
 
@@ -87,47 +96,133 @@ pub fn fee(fee: &str) -> Capacity {
     Capacity::shannons(fee)
 }
 
-// get a nft contract script data with [script_args] fills into args part
-pub fn nft_script(script_args: Vec<u8>) -> Script {
+// get a script which references [contract] by its configured code_hash and hash_type with [script_args] fills into args part
+pub fn contract_script(contract: &Contract, script_args: Vec<u8>) -> Script {
     Script::new_builder()
-        .code_hash(_C.nft.code_hash.clone())
-        .hash_type(ScriptHashType::Data.into())
+        .code_hash(contract.code_hash.clone())
+        .hash_type(contract.hash_type.into())
         .args(Bytes::from(script_args).pack())
         .build()
 }
 
+// get a nft contract script data with [script_args] fills into args part
+pub fn nft_script(script_args: Vec<u8>) -> Script {
+    contract_script(&_C.nft, script_args)
+}
+
 // get a wallet (or ownerlock) contract script data with [script_args] fills into args part
 pub fn wallet_script(script_args: Vec<u8>) -> Script {
-    Script::new_builder()
-        .code_hash(_C.wallet.code_hash.clone())
-        .hash_type(ScriptHashType::Data.into())
-        .args(Bytes::from(script_args).pack())
-        .build()
+    contract_script(&_C.wallet, script_args)
 }
 
 // get a payment contract script data with [script_args] fills into args part
 pub fn payment_script(script_args: Vec<u8>) -> Script {
-    Script::new_builder()
-        .code_hash(_C.payment.code_hash.clone())
-        .hash_type(ScriptHashType::Data.into())
-        .args(Bytes::from(script_args).pack())
-        .build()
+    contract_script(&_C.payment, script_args)
+}
+
+// get payment script args of [pkhash] in nft [series], series 0 is the legacy one which was the only series before
+// composers could run multiple series, so its args keeps the bare pkhash, and the other series require the payment
+// contract entry of "Kabletop.toml" to declare feature "multi_series" since older contracts only accept 20 bytes args
+pub fn payment_args(pkhash: &[u8; 20], series: u8) -> Result<Vec<u8>> {
+    let mut args = pkhash.to_vec();
+    if series > 0 {
+        _C.payment.require("payment", conf::MULTI_SERIES)?;
+        args.push(series);
+    }
+    Ok(args)
+}
+
+// get the nft series from payment script [args] which is made from payment_args
+pub fn payment_series(args: &[u8]) -> u8 {
+    args.get(20).cloned().unwrap_or(0)
+}
+
+// the distance in blocks from the purchase block to the block whose header seeds a commit-reveal package reveal
+pub const REVEAL_DELAY_BLOCKS: u64 = 4;
+
+// get the commitment of user [secret] which is placed into payment cell data at purchase
+pub fn reveal_commitment(secret: &[u8; 32]) -> [u8; 32] {
+    blake2b_256(secret)
+}
+
+// get the commitment from payment cell [data] which is nft_package_count (uint8) | commitment, the legacy payment
+// cell only has nft_package_count so there's no commitment
+pub fn payment_commitment(data: &[u8]) -> Option<[u8; 32]> {
+    if data.len() != 33 {
+        return None
+    }
+    let mut commitment = [0u8; 32];
+    commitment.copy_from_slice(&data[1..]);
+    Some(commitment)
+}
+
+// mix the [header_hash] of the delayed block with user [secret] into the seed of rip_package
+pub fn reveal_seed(header_hash: &Byte32, secret: &[u8; 32]) -> Byte32 {
+    let mut hasher = new_blake2b();
+    hasher.update(&header_hash.raw_data());
+    hasher.update(secret);
+    let mut seed = [0u8; 32];
+    hasher.finalize(&mut seed);
+    seed.pack()
 }
 
 // get a kabletop (or game) contract script data with [script_args] fills into args part
 pub fn kabletop_script(script_args: Vec<u8>) -> Script {
+    contract_script(&_C.kabletop, script_args)
+}
+
+// get a sale lock script with [script_args] fills into args part, the sale contract is optional in config
+pub fn sale_script(script_args: Vec<u8>) -> Result<Script> {
+    let sale = _C.sale.as_ref().ok_or_else(|| KabletopError::ContractNotFound(String::from("sale")))?;
+    Ok(contract_script(sale, script_args))
+}
+
+// get a type-id script with [type_args] fills into args part, the script hash of which is the code_hash of an upgradable contract
+pub fn type_id_script(type_args: Vec<u8>) -> Script {
     Script::new_builder()
-        .code_hash(_C.kabletop.code_hash.clone())
-        .hash_type(ScriptHashType::Data.into())
-        .args(Bytes::from(script_args).pack())
+        .code_hash(TYPE_ID_CODE_HASH.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(type_args).pack())
         .build()
 }
 
+// calculate the args of a new type-id script from the [first_input] of its transaction and its [output_index]
+pub fn calc_type_id_args(first_input: &CellInput, output_index: u64) -> [u8; 32] {
+    let mut hasher = new_blake2b();
+    hasher.update(first_input.as_slice());
+    hasher.update(&output_index.to_le_bytes());
+    let mut type_args = [0u8; 32];
+    hasher.finalize(&mut type_args);
+    type_args
+}
+
+// refuse test-only [capability] on mainnet, which also fails if the configured genesis can't be loaded
+pub fn forbid_mainnet(capability: &'static str) -> Result<()> {
+    if genesis()?.is_mainnet() {
+        return Err(KabletopError::MainnetForbidden(capability));
+    }
+    Ok(())
+}
+
 // get a sighash_blake160 script with [lock_args] fills into args part
-pub fn sighash_script(lock_args: &[u8]) -> Script {
-    _G.sighash_script
+pub fn sighash_script(lock_args: &[u8]) -> Result<Script> {
+    let script = genesis()?
+        .sighash_script
         .clone()
         .as_builder()
         .args(Bytes::from(lock_args.to_vec()).pack())
-        .build()
+        .build();
+    Ok(script)
+}
+
+// the locks owning nfts of user [user_pkhash], which are the sighash lock of user followed by [other_locks] (e.g.
+// multisig or anyone-can-pay) without duplicates
+pub fn owner_locks(user_pkhash: &[u8], other_locks: &[Script]) -> Result<Vec<Script>> {
+    let mut locks = vec![sighash_script(user_pkhash)?];
+    for lock in other_locks {
+        if !locks.contains(lock) {
+            locks.push(lock.clone());
+        }
+    }
+    Ok(locks)
 }
@@ -1,4 +1,8 @@
 pub mod genesis;
 pub mod builder;
 pub mod helper;
-pub mod channel;
\ No newline at end of file
+pub mod channel;
+pub mod deploy;
+pub mod verify;
+pub mod swap;
+pub mod sale;
\ No newline at end of file
@@ -0,0 +1,232 @@
+use crate::{
+    config::{
+        VARS as _C, types::ckb::Contract
+    }, error::{
+        Result, KabletopError
+    }, ckb::{
+        wallet::{
+            keystore, signer
+        }, transaction::helper
+    }
+};
+use ckb_types::{
+    prelude::*, bytes::Bytes, core::{
+        Capacity, TransactionBuilder, TransactionView
+    }, packed::{
+        CellInput, CellOutput, Script
+    }
+};
+use ckb_crypto::secp::Privkey;
+
+/* NFT_SALE_OFFER
+*
+* use [build_tx_list_nft_offer] to put owned nfts on sale at a fixed price, so that the seller can go offline while any
+* buyer fills the offer by [build_tx_fill_nft_offer], and the seller can take the offer back by [build_tx_cancel_nft_offer]
+* before it's filled, open offers are searched by [rpc::get_nft_offers]
+*
+* // OFFER_CELL
+* data:
+* 	  blake160 | blake160 | ...
+* lock:
+* 	  code_hash = sale_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = seller_lock (molecule Script) | setup (uint8, 0 for ckb) | total_value (uint128)
+* type:
+* 	  code_hash = nft_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = nft_wallet_lockhash
+*
+* // SALE_LOCK
+* the sale contract configured as "sale" in Kabletop.toml is an existing order lock (e.g. the dex lock of ckb-dex-scripts)
+* which isn't shipped with this sdk, builders here return ContractNotFound if it's missing, and it unlocks OFFER_CELL by
+* one of:
+*
+* 	  fill:   the output at the same index as OFFER_CELL is locked by seller_lock and holds at least total_value, which
+* 	          is price_ckb plus the capacity of OFFER_CELL, so the seller gets back the capacity of listing
+* 	  cancel: any input of tx is locked by seller_lock
+*
+* the lock never checks where the nfts go on cancel, so seller_lock must be one that only the seller can unlock, offers
+* are listed here under the sighash_blake160 lock of seller only, never under an anyone-can-pay lock
+*/
+
+// list [nfts] owned by [seller] in one offer cell at [price_ckb], nft cells are collected under seller sighash lock
+// and [owner_locks], and only the sighash group is signed here, the groups under [owner_locks] are left for caller
+#[tracing::instrument(skip(seller, nfts, owner_locks), fields(nfts = nfts.len()))]
+pub async fn build_tx_list_nft_offer(
+    seller: &Privkey, nfts: Vec<[u8; 20]>, price_ckb: u64, owner_locks: Vec<Script>
+) -> Result<TransactionView> {
+    let seller_pkhash = helper::privkey_to_pkhash(seller);
+    let seller_lock = helper::sighash_script(&seller_pkhash[..])?;
+    let (offer_output, output_data) = prepare_offer_cell(helper::sale_script(vec![])?, seller_lock, &nfts, price_ckb)?;
+
+    // complete tx
+    let tx = TransactionBuilder::default()
+        .output(offer_output)
+        .output_data(output_data.pack())
+        .build();
+    let owner_locks = helper::owner_locks(&seller_pkhash[..], &owner_locks)?;
+    let tx = helper::complete_tx_with_owned_nft_cells(tx, &owner_locks, &keystore::COMPOSER_PUBHASH, nfts, true).await?;
+    let tx = helper::complete_tx_with_sighash_cells(tx, &seller_pkhash, helper::fee("0.1")).await?;
+    let tx = signer::sign_sighash(tx, seller, vec![])?;
+    Ok(tx)
+}
+
+// fill [offer] by [buyer], the nfts in offer go to [buyer_lock] which could be any lock, buyer pays price_ckb, the
+// capacity of nft cell and the tx fee from its sighash cells
+#[tracing::instrument(skip_all, fields(offer = %offer.out_point, price = offer.price_ckb))]
+pub async fn build_tx_fill_nft_offer(buyer: &Privkey, offer: &helper::NftOffer, buyer_lock: Script) -> Result<TransactionView> {
+    let buyer_pkhash = helper::privkey_to_pkhash(buyer);
+    let tx = prepare_fill_tx(offer, buyer_lock)?;
+
+    // complete tx
+    let tx = helper::add_contract_celldep(tx, &_C.nft).await?;
+    let tx = helper::add_contract_celldep(tx, sale_contract()?).await?;
+    let tx = helper::complete_tx_with_sighash_cells(tx, &buyer_pkhash, helper::fee("0.1")).await?;
+    let tx = signer::sign_sighash(tx, buyer, vec![])?;
+    Ok(tx)
+}
+
+// cancel [offer] listed by [seller], the nfts in offer go back to seller sighash script, and the fee cells of seller
+// under the same script unlock the offer cell
+#[tracing::instrument(skip_all, fields(offer = %offer.out_point))]
+pub async fn build_tx_cancel_nft_offer(seller: &Privkey, offer: &helper::NftOffer) -> Result<TransactionView> {
+    let seller_pkhash = helper::privkey_to_pkhash(seller);
+    if offer.seller_lock != helper::sighash_script(&seller_pkhash[..])? {
+        return Err(KabletopError::InvalidTransaction(format!("offer {} isn't listed by seller", offer.out_point)));
+    }
+    let tx = prepare_cancel_tx(offer);
+
+    // complete tx, the whole capacity of offer cell goes back to seller so the fee always brings in a seller cell
+    let tx = helper::add_contract_celldep(tx, &_C.nft).await?;
+    let tx = helper::add_contract_celldep(tx, sale_contract()?).await?;
+    let tx = helper::complete_tx_with_sighash_cells(tx, &seller_pkhash, helper::fee("0.1")).await?;
+    if tx.inputs().len() < 2 {
+        return Err(KabletopError::InvalidTransaction(String::from("no seller cell to unlock the offer")));
+    }
+    let tx = signer::sign_sighash(tx, seller, vec![])?;
+    Ok(tx)
+}
+
+// prepare the offer cell which lists [nfts] at [price_ckb] for sighash [seller_lock], the args of [sale_lock] is
+// replaced with sale_args, and the offer cell is of exact capacity which goes back to seller along with the price
+fn prepare_offer_cell(sale_lock: Script, seller_lock: Script, nfts: &[[u8; 20]], price_ckb: u64) -> Result<(CellOutput, Bytes)> {
+    if nfts.is_empty() {
+        return Err(KabletopError::InvalidTransaction(String::from("empty nft offer")));
+    }
+    if price_ckb == 0 {
+        return Err(KabletopError::InvalidTransaction(String::from("free nft offer")));
+    }
+
+    // total_value takes the same bytes whatever it is, so the exact capacity is calculated with a zero one
+    let sale_lock = |total_value| {
+        let args = helper::sale_args(&seller_lock, total_value);
+        sale_lock.clone().as_builder().args(Bytes::from(args).pack()).build()
+    };
+    let output_data = Bytes::from(nfts.concat());
+    let offer_output = CellOutput::new_builder()
+        .lock(sale_lock(0))
+        .type_(Some(nft_script()).pack())
+        .build_exact_capacity(Capacity::bytes(output_data.len())?)?;
+    let capacity: u64 = offer_output.capacity().unpack();
+    let total_value = capacity
+        .checked_add(price_ckb)
+        .ok_or_else(|| KabletopError::InvalidTransaction(String::from("nft offer price overflows")))?;
+    let offer_output = offer_output
+        .as_builder()
+        .lock(sale_lock(total_value))
+        .build();
+    Ok((offer_output, output_data))
+}
+
+// prepare the fill tx of [offer] without cell deps and buyer's capacity, the offer cell is the first input and the
+// payment to seller is the first output
+pub fn prepare_fill_tx(offer: &helper::NftOffer, buyer_lock: Script) -> Result<TransactionView> {
+    let outputs = offer.fill_outputs(buyer_lock)?;
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(offer.out_point.clone(), 0))
+        .outputs(outputs.iter().map(|(output, _)| output.clone()))
+        .outputs_data(outputs.iter().map(|(_, data)| data.pack()))
+        .build();
+    Ok(tx)
+}
+
+// prepare the cancel tx of [offer] without cell deps and seller's capacity, which must bring in a seller cell to unlock
+pub fn prepare_cancel_tx(offer: &helper::NftOffer) -> TransactionView {
+    let (output, output_data) = offer.cancel_output();
+    TransactionBuilder::default()
+        .input(CellInput::new(offer.out_point.clone(), 0))
+        .output(output)
+        .output_data(output_data.pack())
+        .build()
+}
+
+fn sale_contract() -> Result<&'static Contract> {
+    _C.sale.as_ref().ok_or_else(|| KabletopError::ContractNotFound(String::from("sale")))
+}
+
+fn nft_script() -> Script {
+    let wallet = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
+    helper::nft_script(wallet.calc_script_hash().raw_data().to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ckb_types::packed::OutPoint;
+
+    fn lock(byte: u8) -> Script {
+        Script::new_builder()
+            .code_hash([byte; 32].pack())
+            .args(Bytes::from(vec![byte; 20]).pack())
+            .build()
+    }
+
+    #[test]
+    fn test_sale_txs() {
+        let (sale_code, seller_lock, buyer_lock) = (lock(9), lock(1), lock(2));
+        let price_ckb = helper::fee("300").as_u64();
+        let nfts = [[3u8; 20], [4u8; 20]];
+        assert!(prepare_offer_cell(sale_code.clone(), seller_lock.clone(), &[], price_ckb).is_err());
+        assert!(prepare_offer_cell(sale_code.clone(), seller_lock.clone(), &nfts, 0).is_err());
+        assert!(prepare_offer_cell(sale_code.clone(), seller_lock.clone(), &nfts, u64::MAX).is_err());
+
+        // list two nfts in an offer cell of exact capacity, whose total value includes that capacity
+        let (offer_output, offer_data) = prepare_offer_cell(sale_code.clone(), seller_lock.clone(), &nfts, price_ckb).expect("offer cell");
+        let offer_capacity: u64 = offer_output.capacity().unpack();
+        assert_eq!(offer_output.occupied_capacity(Capacity::bytes(offer_data.len()).unwrap()).unwrap().as_u64(), offer_capacity);
+        assert_eq!(offer_output.lock().code_hash(), sale_code.code_hash());
+        assert_eq!(offer_output.type_().to_opt(), Some(nft_script()));
+        let offer = helper::NftOffer::from_cell(OutPoint::new([1u8; 32].pack(), 0), offer_output, &offer_data).expect("offer");
+        assert_eq!(offer.seller_lock, seller_lock);
+        assert_eq!(offer.price_ckb, price_ckb);
+        assert_eq!(offer.total_value(), price_ckb + offer_capacity);
+        assert_eq!(offer.nfts, nfts.to_vec());
+
+        // fill spends the offer cell first and pays total_value to seller at the same index, and buyer funds the nft
+        // cell of exact capacity
+        let fill_tx = prepare_fill_tx(&offer, buyer_lock.clone()).expect("fill tx");
+        assert_eq!(fill_tx.inputs().len(), 1);
+        assert_eq!(fill_tx.inputs().get(0).unwrap().previous_output(), offer.out_point);
+        let payment = fill_tx.output(0).unwrap();
+        assert_eq!(payment.lock(), seller_lock);
+        assert!(payment.type_().is_none());
+        assert_eq!(Unpack::<u64>::unpack(&payment.capacity()), offer.total_value());
+        let nft = fill_tx.output(1).unwrap();
+        let nft_data = fill_tx.outputs_data().get(1).unwrap().raw_data();
+        assert_eq!(nft.lock(), buyer_lock);
+        assert_eq!(nft.type_().to_opt(), Some(nft_script()));
+        assert_eq!(nft_data.to_vec(), nfts.concat());
+        assert_eq!(nft.occupied_capacity(Capacity::bytes(nft_data.len()).unwrap()).unwrap().as_u64(), Unpack::<u64>::unpack(&nft.capacity()));
+        let buyer_paid = fill_tx.outputs_capacity().unwrap().as_u64() - offer_capacity;
+        assert_eq!(buyer_paid, price_ckb + Unpack::<u64>::unpack(&nft.capacity()));
+
+        // cancel takes the whole offer cell back to seller
+        let cancel_tx = prepare_cancel_tx(&offer);
+        assert_eq!(cancel_tx.inputs().get(0).unwrap().previous_output(), offer.out_point);
+        let output = cancel_tx.output(0).unwrap();
+        assert_eq!(output.lock(), seller_lock);
+        assert_eq!(output.type_().to_opt(), Some(nft_script()));
+        assert_eq!(Unpack::<u64>::unpack(&output.capacity()), offer_capacity);
+        assert_eq!(cancel_tx.outputs_data().get(0).unwrap().raw_data().to_vec(), nfts.concat());
+    }
+}
@@ -0,0 +1,309 @@
+use crate::{
+    error::{
+        Result, KabletopError
+    }, ckb::{
+        wallet::{
+            keystore, signer
+        }, transaction::{
+            helper, verify::{
+                ChainProvider, NodeProvider
+            }
+        }
+    }
+};
+use ckb_types::{
+    prelude::*, bytes::Bytes, core::{
+        Capacity, TransactionBuilder, TransactionView
+    }, packed::{
+        CellOutput, Script
+    }
+};
+use ckb_crypto::secp::Privkey;
+
+/* SWAP_CELLS
+*
+* use a combine of [prepare_swap_tx, complete_swap_tx, sign_swap_tx] to complish building a tx which swaps seller's
+* nfts for buyer's ckb trustlessly, seller prepares the tx and buyer completes it, then both of them sign it after
+* checking their own part
+*
+* // OUTPUT_CELL_1 (nfts to buyer)
+* data:
+* 	  blake160 | blake160 | ...
+* lock:
+* 	  code_hash = sighash_blake160
+* 	  hash_type = type
+* 	  args 	    = buyer_pubkey_blake160
+* type:
+* 	  code_hash = nft_contract
+* 	  hash_type = data | data1 | type
+* 	  args 	    = nft_wallet_lockhash
+*
+* // OUTPUT_CELL_2 (ckb to seller)
+* lock:
+* 	  code_hash = sighash_blake160
+* 	  hash_type = type
+* 	  args 	    = seller_pubkey_blake160
+* capacity:
+* 	  price_ckb
+*
+* the other outputs are seller's nft change cells (with capacity of seller's nft cells) and buyer's sighash change cell,
+* and buyer pays the capacity of OUTPUT_CELL_1 and tx fee
+*
+* seller's nft cells are collected under seller sighash lock and [seller_locks], each nft change cell goes back to the
+* lock of the nft cells it comes from, and sign_swap_tx only signs the sighash group, so the groups under [seller_locks]
+* are left for seller to sign
+*/
+
+// prepare swap tx with seller-part filled
+#[tracing::instrument(skip(nfts, seller_locks), fields(nfts = nfts.len()))]
+pub async fn prepare_swap_tx(
+    nfts: Vec<[u8; 20]>, price_ckb: u64, seller_pkhash: [u8; 20], seller_locks: Vec<Script>, buyer_pkhash: [u8; 20]
+) -> Result<TransactionView> {
+    if nfts.is_empty() {
+        return Err(KabletopError::SwapMismatch { field: "nfts" });
+    }
+
+    // prepare outputs
+    let output_data = nfts.concat();
+    let buyer_output = CellOutput::new_builder()
+        .lock(helper::sighash_script(&buyer_pkhash[..])?)
+        .type_(Some(nft_script()).pack())
+        .build_exact_capacity(Capacity::bytes(output_data.len())?)?;
+    let seller_output = CellOutput::new_builder()
+        .lock(helper::sighash_script(&seller_pkhash[..])?)
+        .build_exact_capacity(Capacity::zero())?;
+    let occupied: u64 = seller_output.capacity().unpack();
+    if price_ckb < occupied {
+        return Err(KabletopError::InsufficientCapacity {
+            required:  occupied,
+            available: price_ckb
+        });
+    }
+    let seller_output = seller_output
+        .as_builder()
+        .capacity(price_ckb.pack())
+        .build();
+
+    // prepare tx
+    let tx = TransactionBuilder::default()
+        .output(buyer_output)
+        .output(seller_output)
+        .output_data(Bytes::from(output_data).pack())
+        .output_data(Bytes::new().pack())
+        .build();
+    let seller_locks = helper::owner_locks(&seller_pkhash[..], &seller_locks)?;
+    let tx = helper::complete_tx_with_owned_nft_cells(tx, &seller_locks, &keystore::COMPOSER_PUBHASH, nfts, true).await?;
+
+    Ok(tx)
+}
+
+// complete swap tx with buyer-part filled
+#[tracing::instrument(skip(tx, nfts), fields(tx_hash = %tx.hash(), nfts = nfts.len()))]
+pub async fn complete_swap_tx(
+    tx: TransactionView, nfts: Vec<[u8; 20]>, price_ckb: u64, buyer_pkhash: [u8; 20]
+) -> Result<TransactionView> {
+    // check outputs prepared by seller
+    let buyer_lock = helper::sighash_script(&buyer_pkhash[..])?;
+    let buyer_output = tx.output(0).ok_or(KabletopError::SwapMismatch { field: "buyer_output" })?;
+    let buyer_data = tx.outputs_data().get(0).map(|data| data.raw_data()).unwrap_or_default();
+    let buyer_capacity: u64 = buyer_output.capacity().unpack();
+    if buyer_output.lock() != buyer_lock
+        || buyer_output.type_().to_opt() != Some(nft_script())
+        || helper::NftBag::from_bytes(&buyer_data) != helper::NftBag::from(&nfts[..])
+        || buyer_capacity != buyer_output.occupied_capacity(Capacity::bytes(buyer_data.len())?)?.as_u64() {
+        return Err(KabletopError::SwapMismatch { field: "buyer_output" });
+    }
+    let seller_output = tx.output(1).ok_or(KabletopError::SwapMismatch { field: "seller_output" })?;
+    let seller_capacity: u64 = seller_output.capacity().unpack();
+    if seller_capacity != price_ckb {
+        return Err(KabletopError::SwapMismatch { field: "price_ckb" });
+    }
+
+    // the other outputs seller could prepare are the nft change cells paid by seller's nft cells, since anything else
+    // would be paid by buyer
+    let mut input_capacity = 0u64;
+    for input in tx.inputs() {
+        let input = helper::outpoint_to_output(input.previous_output())?;
+        input_capacity += Unpack::<u64>::unpack(&input.capacity());
+    }
+    let change_capacity = tx
+        .outputs()
+        .into_iter()
+        .skip(2)
+        .map(|output| Unpack::<u64>::unpack(&output.capacity()))
+        .sum::<u64>();
+    let unexpected = tx
+        .outputs()
+        .into_iter()
+        .skip(2)
+        .any(|output| output.type_().to_opt().is_some_and(|script| script != nft_script()));
+    if unexpected || change_capacity > input_capacity {
+        return Err(KabletopError::SwapMismatch { field: "outputs" });
+    }
+
+    // complete tx
+    let tx = helper::complete_tx_with_sighash_cells(tx, &buyer_pkhash, helper::fee("0.1")).await?;
+
+    Ok(tx)
+}
+
+// check own part of swap tx and sign the sighash group of it, seller or buyer is decided by [privkey], and [owner_locks]
+// are the other locks of seller whose nft cells may be consumed
+#[tracing::instrument(skip(tx, nfts, privkey, owner_locks), fields(tx_hash = %tx.hash(), nfts = nfts.len()))]
+pub fn sign_swap_tx(
+    tx: TransactionView, nfts: Vec<[u8; 20]>, price_ckb: u64, privkey: &Privkey, owner_locks: Vec<Script>
+) -> Result<TransactionView> {
+    let lock_script = helper::sighash_script(&helper::privkey_to_pkhash(privkey)[..])?;
+    check_swap_tx(&tx, &nfts, price_ckb, &lock_script, &owner_locks, &NodeProvider)?;
+    let tx = signer::sign_sighash(tx, privkey, vec![])?;
+    Ok(tx)
+}
+
+// check the part of sighash [lock_script] in swap [tx] with input cells from [provider], seller must give away exactly
+// [nfts] and receive at least [price_ckb], and buyer must receive [nfts] and spend at most [price_ckb] plus the tx fee,
+// the nft cell to buyer is under buyer lock so its capacity isn't spent
+//
+// own cells are the ones under [lock_script] or any of [owner_locks]
+pub fn check_swap_tx<P: ChainProvider>(
+    tx: &TransactionView, nfts: &[[u8; 20]], price_ckb: u64, lock_script: &Script, owner_locks: &[Script], provider: &P
+) -> Result<()> {
+    let owner_locks = [std::slice::from_ref(lock_script), owner_locks].concat();
+    let nft_script = nft_script();
+    let buyer_output = tx.output(0).ok_or(KabletopError::SwapMismatch { field: "buyer_output" })?;
+    let seller_output = tx.output(1).ok_or(KabletopError::SwapMismatch { field: "seller_output" })?;
+
+    // sum capacity and nfts under own lock
+    let (mut input_capacity, mut output_capacity) = (0u64, 0u64);
+    let (mut input_nfts, mut output_nfts) = (helper::NftBag::new(), helper::NftBag::new());
+    for input in tx.inputs() {
+        let (output, data) = provider.get_cell(&input.previous_output())?;
+        if owner_locks.contains(&output.lock()) {
+            input_capacity += Unpack::<u64>::unpack(&output.capacity());
+            if output.type_().to_opt() == Some(nft_script.clone()) {
+                input_nfts = input_nfts.union(&helper::NftBag::from_bytes(&data));
+            }
+        }
+    }
+    for (output, data) in tx.outputs_with_data_iter() {
+        if owner_locks.contains(&output.lock()) {
+            output_capacity += Unpack::<u64>::unpack(&output.capacity());
+            if output.type_().to_opt() == Some(nft_script.clone()) {
+                output_nfts = output_nfts.union(&helper::NftBag::from_bytes(&data));
+            }
+        }
+    }
+    let swap_nfts = helper::NftBag::from(nfts);
+
+    // check seller or buyer part
+    if &seller_output.lock() == lock_script {
+        if input_nfts.subtract(&output_nfts) != swap_nfts || !output_nfts.subtract(&input_nfts).is_empty() {
+            return Err(KabletopError::SwapMismatch { field: "seller_nfts" });
+        }
+        if output_capacity < input_capacity + price_ckb {
+            return Err(KabletopError::SwapMismatch { field: "seller_capacity" });
+        }
+    } else if &buyer_output.lock() == lock_script {
+        if !swap_nfts.is_subset(&output_nfts.subtract(&input_nfts)) {
+            return Err(KabletopError::SwapMismatch { field: "buyer_nfts" });
+        }
+        let max_spent = price_ckb + helper::fee("0.1").as_u64();
+        if input_capacity > output_capacity + max_spent {
+            return Err(KabletopError::SwapMismatch { field: "buyer_capacity" });
+        }
+    } else {
+        return Err(KabletopError::SwapMismatch { field: "pkhash" });
+    }
+    Ok(())
+}
+
+fn nft_script() -> Script {
+    let wallet = helper::wallet_script(keystore::COMPOSER_PUBHASH.to_vec());
+    helper::nft_script(wallet.calc_script_hash().raw_data().to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ckb::transaction::verify::CellSet;
+    use ckb_types::packed::{
+        CellInput, OutPoint
+    };
+
+    #[test]
+    fn test_check_swap_tx() {
+        let sighash_lock = |pkhash: [u8; 20]| Script::new_builder().args(Bytes::from(pkhash.to_vec()).pack()).build();
+        let (seller_lock, buyer_lock) = (sighash_lock([1u8; 20]), sighash_lock([2u8; 20]));
+        let (nft1, nft2) = ([3u8; 20], [4u8; 20]);
+        let price = helper::fee("100").as_u64();
+        let nft_cell = |lock: &Script, nfts: Vec<[u8; 20]>, capacity: &str| {
+            let output = CellOutput::new_builder()
+                .lock(lock.clone())
+                .type_(Some(nft_script()).pack())
+                .capacity(helper::fee(capacity).pack())
+                .build();
+            (output, Bytes::from(nfts.concat()))
+        };
+        let ckb_cell = |lock: &Script, capacity: u64| {
+            (CellOutput::new_builder().lock(lock.clone()).capacity(capacity.pack()).build(), Bytes::new())
+        };
+
+        // seller gives nft1 from a cell holding nft1 and nft2, buyer pays price, nft cell capacity and fee
+        let inputs = vec![
+            nft_cell(&seller_lock, vec![nft1, nft2], "200"),
+            ckb_cell(&buyer_lock, helper::fee("1000").as_u64())
+        ];
+        let outputs = vec![
+            nft_cell(&buyer_lock, vec![nft1], "150"),
+            ckb_cell(&seller_lock, price),
+            nft_cell(&seller_lock, vec![nft2], "200"),
+            ckb_cell(&buyer_lock, helper::fee("1000").as_u64() - price - helper::fee("150").as_u64() - helper::fee("0.1").as_u64())
+        ];
+        let mut cells = CellSet::default();
+        let mut tx = TransactionBuilder::default();
+        for (i, (output, data)) in inputs.into_iter().enumerate() {
+            let out_point = OutPoint::new([i as u8; 32].pack(), 0);
+            cells = cells.insert_cell(out_point.clone(), output, data);
+            tx = tx.input(CellInput::new_builder().previous_output(out_point).build());
+        }
+        for (output, data) in outputs.clone() {
+            tx = tx.output(output).output_data(data.pack());
+        }
+        let tx = tx.build();
+        check_swap_tx(&tx, &[nft1], price, &seller_lock, &[], &cells).expect("seller check");
+        check_swap_tx(&tx, &[nft1], price, &buyer_lock, &[], &cells).expect("buyer check");
+        assert!(check_swap_tx(&tx, &[nft1], price, &sighash_lock([9u8; 20]), &[], &cells).is_err());
+
+        // seller is paid less, or loses more nfts than agreed
+        assert!(check_swap_tx(&tx, &[nft1], price + 1, &seller_lock, &[], &cells).is_err());
+        let mut bad_outputs = outputs.clone();
+        bad_outputs[2] = nft_cell(&seller_lock, vec![], "200");
+        let bad_tx = tx
+            .as_advanced_builder()
+            .set_outputs(bad_outputs.iter().map(|(output, _)| output.clone()).collect())
+            .set_outputs_data(bad_outputs.iter().map(|(_, data)| data.pack()).collect())
+            .build();
+        assert_eq!(check_swap_tx(&bad_tx, &[nft1], price, &seller_lock, &[], &cells).unwrap_err().code(), 310);
+
+        // seller inflates nft cell to buyer and takes the difference in an extra output paid by buyer
+        let mut bad_outputs = outputs;
+        bad_outputs[0] = nft_cell(&buyer_lock, vec![nft1], "300");
+        bad_outputs[3] = ckb_cell(&buyer_lock, helper::fee("1000").as_u64() - price - helper::fee("450").as_u64() - helper::fee("0.1").as_u64());
+        bad_outputs.push(ckb_cell(&seller_lock, helper::fee("150").as_u64()));
+        let bad_tx = tx
+            .as_advanced_builder()
+            .set_outputs(bad_outputs.iter().map(|(output, _)| output.clone()).collect())
+            .set_outputs_data(bad_outputs.iter().map(|(_, data)| data.pack()).collect())
+            .build();
+        assert_eq!(check_swap_tx(&bad_tx, &[nft1], price, &buyer_lock, &[], &cells).unwrap_err().code(), 310);
+
+        // seller's nft cell is under another lock of seller, which counts only if it's in owner locks
+        let multisig_lock = Script::new_builder()
+            .code_hash([5u8; 32].pack())
+            .args(Bytes::from(vec![6u8; 20]).pack())
+            .build();
+        let (output, data) = nft_cell(&multisig_lock, vec![nft1, nft2], "200");
+        let multisig_cells = cells.insert_cell(OutPoint::new([0u8; 32].pack(), 0), output, data);
+        check_swap_tx(&tx, &[nft1], price, &seller_lock, &[multisig_lock], &multisig_cells).expect("seller check with owner locks");
+        assert_eq!(check_swap_tx(&tx, &[nft1], price, &seller_lock, &[], &multisig_cells).unwrap_err().code(), 310);
+    }
+}
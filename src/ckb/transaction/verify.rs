@@ -0,0 +1,628 @@
+use ckb_types::{
+    prelude::*, bytes::Bytes, core::{
+        Cycle, DepType, HeaderView, HeaderBuilder, TransactionView, BlockView, hardfork::HardForkSwitch, cell::{
+            CellMeta, CellMetaBuilder, ResolvedTransaction
+        }
+    }, packed::{
+        Byte32, CellOutput, OutPoint, OutPointVec, Script, WitnessArgs
+    }
+};
+use ckb_script::{
+    TransactionScriptsVerifier, TxVerifyEnv, ScriptGroupType
+};
+use ckb_traits::{
+    CellDataProvider, HeaderProvider
+};
+use ckb_chain_spec::consensus::ConsensusBuilder;
+use ckb_hash::blake2b_256;
+use std::{
+    collections::HashMap, path::PathBuf, fs, convert::{
+        TryFrom, TryInto
+    }
+};
+use crate::{
+    error::{
+        Result, KabletopError
+    }, config::{
+        VARS as _C, types::ckb::Contract
+    }, ckb::{
+        rpc::methods as rpc, transaction::helper::{
+            self, NFTConfig
+        }
+    }
+};
+
+// the max cycles which all scripts in one block could consume
+const MAX_CYCLES: Cycle = 3_500_000_000;
+
+// supply live cells and headers which a transaction depends on
+pub trait ChainProvider {
+    fn get_cell(&self, out_point: &OutPoint) -> Result<(CellOutput, Bytes)>;
+    fn get_header(&self, block_hash: &Byte32) -> Result<HeaderView>;
+    // the hash of the block which has committed the cell at [out_point]
+    fn get_cell_block_hash(&self, out_point: &OutPoint) -> Result<Byte32>;
+}
+
+// supply cells and headers from the ckb node
+pub struct NodeProvider;
+
+impl ChainProvider for NodeProvider {
+    fn get_cell(&self, out_point: &OutPoint) -> Result<(CellOutput, Bytes)> {
+        let tx = rpc::get_transaction(out_point.tx_hash())?;
+        let index: u32 = out_point.index().unpack();
+        let output = tx
+            .raw()
+            .outputs()
+            .get(index as usize)
+            .ok_or_else(|| KabletopError::CellNotFound(format!("{}", out_point)))?;
+        let data = tx
+            .raw()
+            .outputs_data()
+            .get(index as usize)
+            .map(|data| data.raw_data())
+            .unwrap_or_default();
+        Ok((output, data))
+    }
+
+    fn get_header(&self, block_hash: &Byte32) -> Result<HeaderView> {
+        rpc::get_header(block_hash.clone())
+    }
+
+    fn get_cell_block_hash(&self, out_point: &OutPoint) -> Result<Byte32> {
+        rpc::get_transaction_block_hash(out_point.tx_hash())
+    }
+}
+
+// supply cells and headers collected in memory, which enables verifying without ckb node, [blocks] maps tx hash to
+// the hash of the block committing it
+#[derive(Default)]
+pub struct CellSet {
+    cells:   HashMap<OutPoint, (CellOutput, Bytes)>,
+    headers: HashMap<Byte32, HeaderView>,
+    blocks:  HashMap<Byte32, Byte32>
+}
+
+impl CellSet {
+    pub fn insert_cell(mut self, out_point: OutPoint, output: CellOutput, data: Bytes) -> Self {
+        self.cells.insert(out_point, (output, data));
+        self
+    }
+
+    // the same as insert_cell but the cell is committed in the block of [header], which is inserted as well
+    pub fn insert_committed_cell(mut self, out_point: OutPoint, output: CellOutput, data: Bytes, header: HeaderView) -> Self {
+        self.blocks.insert(out_point.tx_hash(), header.hash());
+        self.insert_cell(out_point, output, data).insert_header(header)
+    }
+
+    // insert all outputs of [tx] as cells
+    pub fn insert_transaction(mut self, tx: &TransactionView) -> Self {
+        for (i, (output, data)) in tx.outputs_with_data_iter().enumerate() {
+            self.cells.insert(OutPoint::new(tx.hash(), i as u32), (output, data));
+        }
+        self
+    }
+
+    pub fn insert_header(mut self, header: HeaderView) -> Self {
+        self.headers.insert(header.hash(), header);
+        self
+    }
+
+    // insert the header and all transaction outputs of [block], a genesis block built from chain spec could supply
+    // all of the system cells in this way
+    pub fn insert_block(self, block: &BlockView) -> Self {
+        let mut cells = block
+            .transactions()
+            .iter()
+            .fold(self, |cells, tx| cells.insert_transaction(tx))
+            .insert_header(block.header());
+        for tx in block.transactions() {
+            cells.blocks.insert(tx.hash(), block.hash());
+        }
+        cells
+    }
+}
+
+impl ChainProvider for CellSet {
+    fn get_cell(&self, out_point: &OutPoint) -> Result<(CellOutput, Bytes)> {
+        self.cells
+            .get(out_point)
+            .cloned()
+            .ok_or_else(|| KabletopError::CellNotFound(format!("{}", out_point)))
+    }
+
+    fn get_header(&self, block_hash: &Byte32) -> Result<HeaderView> {
+        self.headers
+            .get(block_hash)
+            .cloned()
+            .ok_or_else(|| KabletopError::CellNotFound(format!("header {} isn't in cell set", block_hash)))
+    }
+
+    fn get_cell_block_hash(&self, out_point: &OutPoint) -> Result<Byte32> {
+        self.blocks
+            .get(&out_point.tx_hash())
+            .cloned()
+            .ok_or_else(|| KabletopError::CellNotFound(format!("block of cell {} isn't in cell set", out_point)))
+    }
+}
+
+// paths of local contract binaries, which replace the data of their code cell deps while verifying
+#[derive(Default)]
+pub struct ContractBinaries {
+    pub nft:      Option<PathBuf>,
+    pub wallet:   Option<PathBuf>,
+    pub payment:  Option<PathBuf>,
+    pub kabletop: Option<PathBuf>,
+    pub sale:     Option<PathBuf>,
+    pub luacodes: Vec<PathBuf>
+}
+
+impl ContractBinaries {
+    // read all binary files and pair each with the configured out_point and type-id script of its code cell
+    fn load(&self) -> Result<Vec<(OutPoint, Option<Script>, Bytes)>> {
+        let contract = |contract: &Contract| (OutPoint::new(contract.tx_hash.clone(), 0), contract.type_id.clone());
+        let mut paths = vec![
+            (&self.nft, &_C.nft), (&self.wallet, &_C.wallet), (&self.payment, &_C.payment), (&self.kabletop, &_C.kabletop)
+        ]
+            .into_iter()
+            .filter_map(|(path, config)| path.as_ref().map(|path| (path, contract(config))))
+            .collect::<Vec<_>>();
+        if let Some(path) = &self.sale {
+            let sale = _C.sale.as_ref().ok_or_else(|| KabletopError::ContractNotFound(String::from("sale")))?;
+            paths.push((path, contract(sale)));
+        }
+        for (i, path) in self.luacodes.iter().enumerate() {
+            let luacode = _C.luacodes.get(i).ok_or_else(|| KabletopError::Config(format!("luacode #{} isn't configured", i)))?;
+            paths.push((path, (OutPoint::new(luacode.tx_hash.clone(), 0), None)));
+        }
+        paths
+            .into_iter()
+            .map(|(path, (out_point, type_id))| Ok((out_point, type_id, Bytes::from(fs::read(path)?))))
+            .collect()
+    }
+}
+
+// the verification result of one script group, which is the consumed cycles or the script error
+pub struct ScriptReport {
+    pub group_type:  ScriptGroupType,
+    pub script:      Script,
+    pub script_hash: Byte32,
+    pub result:      Result<Cycle, String>
+}
+
+// internal data loader for ckb-script which holds every resolved cell data and header dep
+struct DataLoader {
+    cells:   HashMap<OutPoint, Bytes>,
+    headers: HashMap<Byte32, HeaderView>
+}
+
+impl CellDataProvider for DataLoader {
+    fn get_cell_data(&self, out_point: &OutPoint) -> Option<Bytes> {
+        self.cells.get(out_point).cloned()
+    }
+
+    fn get_cell_data_hash(&self, out_point: &OutPoint) -> Option<Byte32> {
+        self.cells.get(out_point).map(|data| CellOutput::calc_data_hash(data))
+    }
+}
+
+impl HeaderProvider for DataLoader {
+    fn get_header(&self, hash: &Byte32) -> Option<HeaderView> {
+        self.headers.get(hash).cloned()
+    }
+}
+
+// run every lock and type script group of [tx] in local ckb-vm, the inputs, cell deps and header deps are resolved
+// from [provider], and code cell deps of kabletop contracts or luacodes are replaced with local [binaries]
+pub fn verify_transaction_locally<P: ChainProvider>(
+    tx: &TransactionView, binaries: &ContractBinaries, provider: &P
+) -> Result<Vec<ScriptReport>> {
+    let binaries = binaries.load()?;
+    let mut loader = DataLoader {
+        cells:   HashMap::new(),
+        headers: HashMap::new()
+    };
+    let mut resolve = |out_point: OutPoint, is_code: bool| -> Result<CellMeta> {
+        let (output, mut data) = provider.get_cell(&out_point)?;
+        if is_code {
+            let binary = binaries
+                .iter()
+                .find(|(code_out_point, type_id, _)| {
+                    code_out_point == &out_point
+                        || (type_id.is_some() && type_id == &output.type_().to_opt())
+                });
+            if let Some((_, _, binary)) = binary {
+                data = binary.clone();
+            }
+        }
+        loader.cells.insert(out_point.clone(), data.clone());
+        Ok(CellMetaBuilder::from_cell_output(output, data).out_point(out_point).build())
+    };
+
+    // resolve inputs and cell deps, the cell deps in dep group are expanded
+    let mut resolved_inputs = vec![];
+    for input in tx.inputs().into_iter() {
+        resolved_inputs.push(resolve(input.previous_output(), false)?);
+    }
+    let mut resolved_cell_deps = vec![];
+    let mut resolved_dep_groups = vec![];
+    for cell_dep in tx.cell_deps_iter() {
+        if Into::<u8>::into(cell_dep.dep_type()) == DepType::DepGroup as u8 {
+            let dep_group = resolve(cell_dep.out_point(), false)?;
+            let out_points = OutPointVec::from_slice(&dep_group.mem_cell_data.clone().unwrap_or_default())?;
+            for out_point in out_points.into_iter() {
+                resolved_cell_deps.push(resolve(out_point, true)?);
+            }
+            resolved_dep_groups.push(dep_group);
+        } else {
+            resolved_cell_deps.push(resolve(cell_dep.out_point(), true)?);
+        }
+    }
+    for block_hash in tx.header_deps_iter() {
+        let header = provider.get_header(&block_hash)?;
+        loader.headers.insert(block_hash, header);
+    }
+    let rtx = ResolvedTransaction {
+        transaction: tx.clone(), resolved_cell_deps, resolved_inputs, resolved_dep_groups
+    };
+
+    // enable all hardfork features from the first epoch, so that scripts with hash_type "data1" could run
+    let hardfork_switch = HardForkSwitch::new_without_any_enabled()
+        .as_builder()
+        .rfc_0028(0)
+        .rfc_0029(0)
+        .rfc_0030(0)
+        .rfc_0031(0)
+        .rfc_0032(0)
+        .rfc_0036(0)
+        .rfc_0038(0)
+        .build()
+        .map_err(|err| KabletopError::InvalidTransaction(format!("hardfork switch: {}", err)))?;
+    let consensus = ConsensusBuilder::default()
+        .hardfork_switch(hardfork_switch)
+        .build();
+    let tx_env = TxVerifyEnv::new_submit(&HeaderBuilder::default().build());
+
+    // verify script groups one by one, lock groups go first
+    let verifier = TransactionScriptsVerifier::new(&rtx, &consensus, &loader, &tx_env);
+    let mut reports = verifier
+        .groups()
+        .map(|(group_type, script_hash, group)| ScriptReport {
+            group_type,
+            script:      group.script.clone(),
+            script_hash: script_hash.clone(),
+            result:      verifier
+                .verify_single(group_type, script_hash, MAX_CYCLES)
+                .map_err(|err| err.to_string())
+        })
+        .collect::<Vec<_>>();
+    reports.sort_by_key(|report| (report.group_type == ScriptGroupType::Type, report.script_hash.raw_data()));
+    Ok(reports)
+}
+
+// recompute the nfts which reveal [tx] should produce from the data of consumed wallet cell [wallet_data], the nft config
+// data [config_data] and the block [header] of the payment, and compare them with the nft output data, the verified nfts
+// are returned
+//
+// the wallet output is the first output of [tx] and the nft output is the nft cell whose type args is the lock hash of
+// wallet output, and it must carry exactly the package count paid in [wallet_data]
+//
+// for a commit-reveal payment the user secret is in the input_type of the first witness which must match the commitment
+// in [wallet_data], and [header] is the delayed block header instead
+pub fn verify_reveal(tx: &TransactionView, wallet_data: &[u8], config_data: Bytes, header: &HeaderView) -> Result<Vec<[u8; 20]>> {
+    if !tx.header_deps_iter().any(|hash| hash == header.hash()) {
+        return Err(KabletopError::InvalidReveal(format!("header {} isn't in header deps", header.hash())));
+    }
+    let nft_config = NFTConfig::try_from(config_data)?;
+    let (_, nft_data) = reveal_nft_output(tx)?;
+
+    // rip the paid packages with the header
+    let package_count = match wallet_data.first() {
+        Some(&count) if count > 0 => count,
+        _ => return Err(KabletopError::InvalidReveal(String::from("wallet cell has no paid package")))
+    };
+    let seed = match (helper::payment_commitment(wallet_data), reveal_secret(tx)) {
+        (None, None) => header.hash(),
+        (Some(commitment), Some(secret)) if helper::reveal_commitment(&secret) == commitment => {
+            helper::reveal_seed(&header.hash(), &secret)
+        },
+        _ => return Err(KabletopError::CommitmentMismatch)
+    };
+    let expected_nfts = nft_config.rip_package(seed, package_count);
+    if expected_nfts != nft_data {
+        return Err(KabletopError::InvalidReveal(String::from("nft output data mismatched with the ripped nfts")));
+    }
+    let nfts = nft_data
+        .chunks(20)
+        .map(|nft| {
+            let mut blake160 = [0u8; 20];
+            blake160.copy_from_slice(nft);
+            blake160
+        })
+        .collect();
+    Ok(nfts)
+}
+
+// the same as verify_reveal but the wallet cell input, the config cell dep and the header dep of [tx] are resolved from
+// [provider], the wallet cell is the first input, the config cell is the cell dep which has the same lock as the wallet
+// output and the composer payment script of the same series as the wallet output, the first header dep must be the
+// block committing the wallet cell (payment block), and the last header dep seeds the lotteries which must be
+// exactly REVEAL_DELAY_BLOCKS after the payment block for a commit-reveal payment, so that a user knowing the secret
+// can't pick the best of later headers
+pub fn verify_reveal_with_provider<P: ChainProvider>(tx: &TransactionView, provider: &P) -> Result<Vec<[u8; 20]>> {
+    let wallet_input = tx
+        .inputs()
+        .get(0)
+        .ok_or_else(|| KabletopError::InvalidReveal(String::from("tx's input is empty")))?;
+    let (_, wallet_data) = provider.get_cell(&wallet_input.previous_output())?;
+    let wallet_output = tx
+        .output(0)
+        .ok_or_else(|| KabletopError::InvalidReveal(String::from("tx's output is empty")))?;
+
+    // the config cell is under composer payment script of the series in wallet output
+    let series = wallet_output
+        .type_()
+        .to_opt()
+        .map(|script| helper::payment_series(&script.args().raw_data()))
+        .ok_or_else(|| KabletopError::InvalidReveal(String::from("wallet output has no payment script")))?;
+    let composer_pkhash: [u8; 20] = wallet_output
+        .lock()
+        .args()
+        .raw_data()
+        .as_ref()
+        .try_into()
+        .map_err(|_| KabletopError::InvalidReveal(String::from("wallet lock args isn't a pubkey hash")))?;
+    let config_script = helper::payment_script(helper::payment_args(&composer_pkhash, series)?);
+    let mut config_data = None;
+    for cell_dep in tx.cell_deps_iter() {
+        if Into::<u8>::into(cell_dep.dep_type()) == DepType::DepGroup as u8 {
+            continue
+        }
+        if let Ok((output, data)) = provider.get_cell(&cell_dep.out_point()) {
+            if output.lock().as_slice() == wallet_output.lock().as_slice() && output.type_().to_opt() == Some(config_script.clone()) {
+                config_data = Some(data);
+                break
+            }
+        }
+    }
+    let config_data = config_data.ok_or_else(|| KabletopError::InvalidReveal(String::from("no config cell in cell deps")))?;
+
+    // the first header dep must be the payment block
+    let header_hashes = tx.header_deps_iter().collect::<Vec<_>>();
+    let header_hash = header_hashes
+        .last()
+        .ok_or_else(|| KabletopError::InvalidReveal(String::from("tx's header deps is empty")))?;
+    let payment_block_hash = provider.get_cell_block_hash(&wallet_input.previous_output())?;
+    if header_hashes[0] != payment_block_hash {
+        return Err(KabletopError::InvalidReveal(format!("header {} isn't the payment block", header_hashes[0])));
+    }
+    let header = provider.get_header(header_hash)?;
+    if reveal_secret(tx).is_some() {
+        let payment_header = provider.get_header(&payment_block_hash)?;
+        if header.number() != payment_header.number() + helper::REVEAL_DELAY_BLOCKS {
+            return Err(KabletopError::InvalidReveal(format!("header #{} isn't the delayed block of payment", header.number())));
+        }
+    }
+    verify_reveal(tx, &wallet_data, config_data, &header)
+}
+
+// get the user secret of commit-reveal from the first witness of reveal [tx]
+fn reveal_secret(tx: &TransactionView) -> Option<[u8; 32]> {
+    let witness: Bytes = tx.witnesses().get(0)?.unpack();
+    let secret = WitnessArgs::from_slice(&witness)
+        .ok()?
+        .input_type()
+        .to_opt()?
+        .raw_data();
+    if secret.len() != 32 {
+        return None
+    }
+    let mut buf = [0u8; 32];
+    buf.copy_from_slice(&secret);
+    Some(buf)
+}
+
+// search the nft output of reveal [tx] by the nft script whose args is the lock hash of the first output
+fn reveal_nft_output(tx: &TransactionView) -> Result<(CellOutput, Bytes)> {
+    let wallet_output = tx
+        .output(0)
+        .ok_or_else(|| KabletopError::InvalidReveal(String::from("tx's output is empty")))?;
+    let nft_script = helper::nft_script(blake2b_256(wallet_output.lock().as_slice()).to_vec());
+    tx.outputs_with_data_iter()
+        .find(|(output, _)| output.type_().to_opt().as_ref() == Some(&nft_script))
+        .ok_or_else(|| KabletopError::InvalidReveal(String::from("no nft output found")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ckb_types::core::{
+        Capacity, TransactionBuilder
+    };
+    use ckb_chain_spec::ChainSpec;
+    use ckb_resource::Resource;
+    use crate::ckb::transaction::{
+        genesis::Genesis, helper
+    };
+
+    #[test]
+    fn test_verify_transaction_locally() {
+        // prepare system cells from the bundled devnet chain spec
+        let block = ChainSpec::load_from(&Resource::bundled(String::from("specs/dev.toml")))
+            .expect("load dev spec")
+            .build_genesis()
+            .expect("build genesis");
+        let genesis = Genesis::from_block(block.clone()).expect("genesis from block");
+
+        // prepare an unsigned sighash input and a new type-id output
+        let lock_script = genesis.sighash_script
+            .as_builder()
+            .args(Bytes::from(vec![0u8; 20]).pack())
+            .build();
+        let input_out_point = OutPoint::new([1u8; 32].pack(), 0);
+        let input_cell = CellOutput::new_builder()
+            .lock(lock_script.clone())
+            .capacity(Capacity::bytes(1000).unwrap().pack())
+            .build();
+        let input = ckb_types::packed::CellInput::new_builder()
+            .previous_output(input_out_point.clone())
+            .build();
+        let type_script = helper::type_id_script(helper::calc_type_id_args(&input, 0).to_vec());
+        let output = CellOutput::new_builder()
+            .lock(lock_script.clone())
+            .type_(Some(type_script.clone()).pack())
+            .capacity(Capacity::bytes(999).unwrap().pack())
+            .build();
+        let tx = TransactionBuilder::default()
+            .input(input)
+            .output(output)
+            .output_data(Bytes::new().pack())
+            .cell_dep(genesis.sighash_celldep.clone())
+            .build();
+
+        // the type-id script passes and the unsigned sighash lock fails
+        let cells = CellSet::default()
+            .insert_block(&block)
+            .insert_cell(input_out_point, input_cell, Bytes::new());
+        let reports = verify_transaction_locally(&tx, &ContractBinaries::default(), &cells).expect("verify");
+        assert_eq!(reports.len(), 2);
+        assert!(reports[0].group_type == ScriptGroupType::Lock);
+        assert_eq!(reports[0].script, lock_script);
+        assert!(reports[0].result.is_err());
+        assert!(reports[1].group_type == ScriptGroupType::Type);
+        assert_eq!(reports[1].script, type_script);
+        assert!(reports[1].result.is_ok());
+    }
+
+    #[test]
+    fn test_verify_reveal() {
+        // prepare a reveal tx which rips 2 packages with the header dep
+        let nft_config = helper::NFTConfig::new(100, 3, vec![([1u8; 20], 100), ([2u8; 20], 200), ([3u8; 20], 255)]);
+        let header = HeaderBuilder::default().number(1024u64.pack()).build();
+        let wallet_lock = Script::new_builder()
+            .args(Bytes::from(vec![7u8; 20]).pack())
+            .build();
+        let wallet_output = CellOutput::new_builder()
+            .lock(wallet_lock.clone())
+            .type_(Some(helper::payment_script(vec![8u8; 20])).pack())
+            .build();
+        let nft_type = helper::nft_script(wallet_lock.calc_script_hash().raw_data().to_vec());
+        let nft_output = CellOutput::new_builder()
+            .type_(Some(nft_type).pack())
+            .build();
+        let wallet_out_point = OutPoint::new([1u8; 32].pack(), 0);
+        let wallet_data = vec![2u8];
+        let config_out_point = OutPoint::new([2u8; 32].pack(), 0);
+        let config_output = CellOutput::new_builder()
+            .lock(wallet_lock.clone())
+            .type_(Some(helper::payment_script(vec![7u8; 20])).pack())
+            .build();
+
+        // the config cell of another series comes first in cell deps
+        let other_config_out_point = OutPoint::new([3u8; 32].pack(), 0);
+        let other_config_output = config_output
+            .clone()
+            .as_builder()
+            .type_(Some(helper::payment_script([&[7u8; 20][..], &[1u8][..]].concat())).pack())
+            .build();
+        let other_config = helper::NFTConfig::new(100, 3, vec![([4u8; 20], 255)]);
+        let nfts = nft_config.rip_package(header.hash(), 2);
+        let tx = TransactionBuilder::default()
+            .input(ckb_types::packed::CellInput::new(wallet_out_point.clone(), 0))
+            .output(wallet_output.clone())
+            .output_data(Bytes::from(vec![0u8]).pack())
+            .output(nft_output)
+            .output_data(nfts.pack())
+            .cell_dep(ckb_types::packed::CellDep::new_builder().out_point(other_config_out_point.clone()).build())
+            .cell_dep(ckb_types::packed::CellDep::new_builder().out_point(config_out_point.clone()).build())
+            .header_dep(header.hash())
+            .build();
+
+        // both from raw data and from provider
+        let verified = verify_reveal(&tx, &wallet_data, nft_config.to_ckb_bytes(), &header).expect("verify reveal");
+        assert_eq!(verified.len(), 6);
+        let cells = CellSet::default()
+            .insert_committed_cell(wallet_out_point.clone(), wallet_output.clone(), Bytes::from(wallet_data.clone()), header.clone())
+            .insert_cell(other_config_out_point, other_config_output, other_config.to_ckb_bytes())
+            .insert_cell(config_out_point.clone(), config_output.clone(), nft_config.to_ckb_bytes());
+        assert_eq!(verify_reveal_with_provider(&tx, &cells).expect("verify reveal with provider"), verified);
+
+        // the header dep isn't the block committing the wallet cell
+        let other_header = HeaderBuilder::default().number(1025u64.pack()).build();
+        let other_header_tx = tx.as_advanced_builder().set_header_deps(vec![other_header.hash()]).build();
+        let other_cells = CellSet::default()
+            .insert_committed_cell(wallet_out_point.clone(), wallet_output.clone(), Bytes::from(wallet_data.clone()), header.clone())
+            .insert_cell(config_out_point.clone(), config_output.clone(), nft_config.to_ckb_bytes())
+            .insert_header(other_header.clone());
+        assert_eq!(verify_reveal_with_provider(&other_header_tx, &other_cells).unwrap_err().code(), 308);
+
+        // fewer packages delivered than paid
+        assert_eq!(verify_reveal(&tx, &[3u8], nft_config.to_ckb_bytes(), &header).unwrap_err().code(), 308);
+
+        // nft output under a type script which isn't the nft contract
+        let fake_type = helper::nft_script(wallet_lock.calc_script_hash().raw_data().to_vec())
+            .as_builder()
+            .code_hash([9u8; 32].pack())
+            .build();
+        let mut outputs = tx.outputs().into_iter().collect::<Vec<_>>();
+        outputs[1] = outputs[1]
+            .clone()
+            .as_builder()
+            .type_(Some(fake_type).pack())
+            .build();
+        let fake_tx = tx.as_advanced_builder().set_outputs(outputs).build();
+        assert_eq!(verify_reveal(&fake_tx, &wallet_data, nft_config.to_ckb_bytes(), &header).unwrap_err().code(), 308);
+
+        // tampered nft output data
+        let mut tampered = nfts.to_vec();
+        tampered[..20].copy_from_slice(&[9u8; 20]);
+        let mut outputs_data = tx.outputs_data().into_iter().collect::<Vec<_>>();
+        outputs_data[1] = Bytes::from(tampered).pack();
+        let bad_tx = tx.as_advanced_builder().set_outputs_data(outputs_data).build();
+        assert_eq!(verify_reveal(&bad_tx, &wallet_data, nft_config.to_ckb_bytes(), &header).unwrap_err().code(), 308);
+
+        // header not in header deps
+        assert_eq!(verify_reveal(&tx, &wallet_data, nft_config.to_ckb_bytes(), &other_header).unwrap_err().code(), 308);
+
+        // commit-reveal with the secret in witness and the delayed header
+        let secret = [5u8; 32];
+        let delayed_header = HeaderBuilder::default().number((1024 + helper::REVEAL_DELAY_BLOCKS).pack()).build();
+        let mut outputs_data = tx.outputs_data().into_iter().collect::<Vec<_>>();
+        outputs_data[1] = nft_config.rip_package(helper::reveal_seed(&delayed_header.hash(), &secret), 2).pack();
+        let witness = WitnessArgs::new_builder()
+            .input_type(Some(Bytes::from(secret.to_vec())).pack())
+            .build();
+        let secret_tx = tx
+            .as_advanced_builder()
+            .set_outputs_data(outputs_data)
+            .header_dep(delayed_header.hash())
+            .witness(witness.as_bytes().pack())
+            .build();
+        let secret_wallet_data = [&[2u8][..], &helper::reveal_commitment(&secret)[..]].concat();
+        verify_reveal(&secret_tx, &secret_wallet_data, nft_config.to_ckb_bytes(), &delayed_header).expect("verify commit-reveal");
+        let cells = cells
+            .insert_cell(wallet_out_point.clone(), wallet_output.clone(), Bytes::from(secret_wallet_data.clone()))
+            .insert_header(delayed_header.clone());
+        verify_reveal_with_provider(&secret_tx, &cells).expect("verify commit-reveal with provider");
+        assert!(verify_reveal(&secret_tx, &secret_wallet_data, nft_config.to_ckb_bytes(), &header).is_err());
+        let other_commitment = [&[2u8][..], &[0u8; 32][..]].concat();
+        assert_eq!(verify_reveal(&secret_tx, &other_commitment, nft_config.to_ckb_bytes(), &delayed_header).unwrap_err().code(), 309);
+
+        // a header earlier or later than the delayed block is rejected even if the nfts are ripped by it
+        for number in [1023 + helper::REVEAL_DELAY_BLOCKS, 1025 + helper::REVEAL_DELAY_BLOCKS] {
+            let other_header = HeaderBuilder::default().number(number.pack()).build();
+            let mut outputs_data = secret_tx.outputs_data().into_iter().collect::<Vec<_>>();
+            outputs_data[1] = nft_config.rip_package(helper::reveal_seed(&other_header.hash(), &secret), 2).pack();
+            let other_tx = secret_tx
+                .as_advanced_builder()
+                .set_header_deps(vec![header.hash(), other_header.hash()])
+                .set_outputs_data(outputs_data)
+                .build();
+            verify_reveal(&other_tx, &secret_wallet_data, nft_config.to_ckb_bytes(), &other_header).expect("verify raw data");
+            let other_cells = CellSet::default()
+                .insert_committed_cell(wallet_out_point.clone(), wallet_output.clone(), Bytes::from(secret_wallet_data.clone()), header.clone())
+                .insert_cell(config_out_point.clone(), config_output.clone(), nft_config.to_ckb_bytes())
+                .insert_header(other_header);
+            assert_eq!(verify_reveal_with_provider(&other_tx, &other_cells).unwrap_err().code(), 308);
+        }
+    }
+}
@@ -6,7 +6,9 @@ use ckb_types::{
 };
 use ckb_hash::new_blake2b;
 use ckb_crypto::secp::Privkey;
-use crate::ckb::transaction::helper;
+use crate::{
+    error::Result, ckb::transaction::helper
+};
 use std::collections::HashMap;
 
 // sign a whole [tx] using private [key], the [extra_witnesses] is some external args which just placed into witness part
@@ -59,9 +61,18 @@ pub fn sign(
         .map(|witness| witness.as_bytes().pack())
         .collect::<Vec<_>>();
     signed_witnesses.append(&mut extra_witnesses);
-    tx.as_advanced_builder()
+    let tx = tx.as_advanced_builder()
         .set_witnesses(signed_witnesses)
-        .build()
+        .build();
+    tracing::debug!(tx_hash = %tx.hash(), inputs = tx.inputs().len(), "transaction signed");
+    tx
+}
+
+// sign only the input group under sighash_blake160 lock of [key], the groups under other owner locks (e.g. multisig)
+// need their own witness format so they are left for the caller to sign
+pub fn sign_sighash(tx: TransactionView, key: &Privkey, extra_witnesses: Vec<WitnessArgs>) -> Result<TransactionView> {
+    let lock_script = helper::sighash_script(&helper::privkey_to_pkhash(key)[..])?;
+    Ok(sign(tx, key, extra_witnesses, Box::new(move |input| input.lock() == lock_script)))
 }
 
 // sign the every single input data in [tx] and get the signed bytes
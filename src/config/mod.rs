@@ -1,38 +1,90 @@
 use toml;
-use anyhow::Result;
+use crate::error::{
+    Result, KabletopError
+};
 use std::{
-    fs::File, io::prelude::*
+    fs::{
+        self, File
+    }, io::{
+        prelude::*, ErrorKind
+    }, path::Path
 };
 
-mod types;
+pub mod types;
 use types::{
-    Vars, ckb
+    Vars, Contract, Luacode, ckb
 };
 
+pub const CONFIG_PATH: &str = "Kabletop.toml";
+
 lazy_static! {
     pub static ref VARS: ckb::Vars = match load() {
 		Ok(vars) => vars,
 		Err(err) => {
-			println!("loading config error: {}", err);
+			tracing::error!(%err, "loading config error");
 			ckb::Vars::default()
 		}
 	};
 }
 
 fn load() -> Result<ckb::Vars> {
-    let mut file = File::open("Kabletop.toml")?;
+    let mut file = File::open(CONFIG_PATH)?;
     let mut content = String::new();
     file.read_to_string(&mut content)?;
     let vars: Vars = toml::from_str(content.as_str())?;
     let vars = ckb::Vars::from(vars);
+    vars.check()?;
     Ok(vars)
 }
 
+// write deployed [contracts] (named as "nft", "wallet", "payment", "kabletop" or "sale") and [luacodes] into the config
+// file at [path] (CONFIG_PATH in general), the luacodes list will be replaced only if [luacodes] isn't empty, and other
+// entries in the file stay untouched, a missing file is created but an unreadable one is never overwritten
+//
+// the features declared for a contract in the file are kept if the updated contract carries none, since a deployment
+// can't tell which features its binary supports
+pub fn update<P: AsRef<Path>>(path: P, contracts: Vec<(&str, Contract)>, luacodes: Vec<Luacode>) -> Result<()> {
+    let path = path.as_ref();
+    let mut vars = match fs::read_to_string(path) {
+        Ok(content) => toml::from_str::<toml::Value>(content.as_str())?,
+        Err(err) if err.kind() == ErrorKind::NotFound => toml::Value::Table(toml::value::Table::new()),
+        Err(err)    => return Err(err.into())
+    };
+    let table = vars
+        .as_table_mut()
+        .ok_or_else(|| KabletopError::Config(format!("{} isn't in table format", path.display())))?;
+    for (name, contract) in contracts {
+        let mut contract = toml::Value::try_from(contract)?;
+        let features = table
+            .get(name)
+            .and_then(|previous| previous.get("features"))
+            .cloned();
+        if let (Some(contract), Some(features)) = (contract.as_table_mut(), features) {
+            contract.entry("features").or_insert(features);
+        }
+        table.insert(String::from(name), contract);
+    }
+    if !luacodes.is_empty() {
+        table.insert(String::from("luacodes"), toml::Value::try_from(luacodes)?);
+    }
+    fs::write(path, toml::to_string_pretty(&vars)?)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
-    use super::load;
+    use super::{
+        load, update, ckb::{
+            self, Contract
+        }, types as conf
+    };
     use hex;
-	use ckb_types::prelude::Entity;
+	use ckb_types::{
+        prelude::{
+            Entity, Pack
+        }, core::ScriptHashType
+    };
+    use crate::ckb::transaction::helper;
 
     #[test]
     fn test_load() {
@@ -49,4 +101,100 @@ mod test {
         let expected_tx_hash = hex::decode("e55ae885933943744c12b85de591f41e970fb46bc99043d89e6bbfefad2a2586").expect("hex");
         assert_eq!(luacode.tx_hash.as_slice(), expected_tx_hash.as_slice(), "bad luacode tx_hash");
     }
+
+    #[test]
+    fn test_contract_hash_type() {
+        let type_id = helper::type_id_script(vec![1u8; 32]);
+        let contract = |code_hash, hash_type, type_id| Contract {
+            tx_hash: [0u8; 32].pack(), code_hash, hash_type, type_id, features: vec![]
+        };
+        contract([2u8; 32].pack(), ScriptHashType::Data, None).check("nft").expect("data");
+        contract([2u8; 32].pack(), ScriptHashType::Data1, None).check("nft").expect("data1");
+        contract(type_id.calc_script_hash(), ScriptHashType::Type, Some(type_id.clone())).check("nft").expect("type");
+
+        // type_args with data, type without type_args, and type with a code_hash which isn't the type-id hash
+        let err = contract([2u8; 32].pack(), ScriptHashType::Data, Some(type_id.clone())).check("nft").unwrap_err();
+        assert_eq!(err.code(), 100);
+        assert!(contract(type_id.calc_script_hash(), ScriptHashType::Type, None).check("nft").is_err());
+        assert!(contract([2u8; 32].pack(), ScriptHashType::Type, Some(type_id)).check("nft").is_err());
+    }
+
+    #[test]
+    fn test_contract_features() {
+        let content = "tx_hash = \"00\"\ncode_hash = \"00\"\nfeatures = [\"purchase_refund\"]\n";
+        let contract: conf::Contract = toml::from_str(content).expect("parse");
+        assert_eq!(contract.features, vec![String::from(ckb::PURCHASE_REFUND)]);
+
+        // features are optional, and left out when writing a contract without any
+        let mut contract: conf::Contract = toml::from_str("tx_hash = \"00\"\ncode_hash = \"00\"\n").expect("parse");
+        assert!(contract.features.is_empty());
+        assert!(!toml::to_string(&contract).expect("serialize").contains("features"));
+        contract.features.push(String::from(ckb::PURCHASE_REFUND));
+        assert!(toml::to_string(&contract).expect("serialize").contains("features"));
+
+        let contract = Contract {
+            tx_hash:   [0u8; 32].pack(),
+            code_hash: [0u8; 32].pack(),
+            hash_type: ScriptHashType::Data,
+            type_id:   None,
+            features:  vec![String::from(ckb::PURCHASE_REFUND)]
+        };
+        assert!(contract.supports(ckb::PURCHASE_REFUND));
+        assert!(!contract.supports("unknown"));
+        contract.require("payment", ckb::PURCHASE_REFUND).expect("require");
+        assert_eq!(contract.require("payment", ckb::COMMIT_REVEAL).unwrap_err().code(), 104);
+    }
+
+    #[test]
+    fn test_update() {
+        let path = std::env::temp_dir().join(format!("kabletop-update-{}.toml", std::process::id()));
+        std::fs::write(&path, "[common]\nckb_uri = \"http://127.0.0.1:8114\"\n\n[nft]\ntx_hash = \"00\"\ncode_hash = \"00\"\nfeatures = [\"store_close\"]\n")
+            .expect("write config");
+        let contract = || conf::Contract {
+            tx_hash:   String::from("11"),
+            code_hash: String::from("22"),
+            hash_type: ScriptHashType::Type.into(),
+            type_args: Some(String::from("33")),
+            features:  vec![]
+        };
+        let luacode = conf::Luacode { tx_hash: String::from("44"), data_hash: String::from("55") };
+        update(&path, vec![("nft", contract()), ("wallet", contract())], vec![luacode]).expect("update");
+
+        // updated entries are replaced and the others stay untouched
+        let content = std::fs::read_to_string(&path).expect("read config");
+        let vars: toml::Value = toml::from_str(&content).expect("parse config");
+        assert_eq!(vars["common"]["ckb_uri"].as_str(), Some("http://127.0.0.1:8114"));
+        let nft: conf::Contract = vars["nft"].clone().try_into().expect("nft");
+        assert_eq!((nft.tx_hash.as_str(), nft.code_hash.as_str(), nft.type_args.as_deref()), ("11", "22", Some("33")));
+        assert_eq!(vars["wallet"]["hash_type"].as_str(), Some("type"));
+        assert_eq!(vars["luacodes"][0]["data_hash"].as_str(), Some("55"));
+
+        // declared features are kept unless the updated contract carries its own
+        assert_eq!(nft.features, vec![String::from(ckb::STORE_CLOSE)]);
+        assert!(vars["wallet"].get("features").is_none());
+        let featured = conf::Contract { features: vec![String::from(ckb::MULTI_SERIES)], ..contract() };
+        update(&path, vec![("nft", featured)], vec![]).expect("update");
+        let vars: toml::Value = toml::from_str(&std::fs::read_to_string(&path).expect("read config")).expect("parse config");
+        let nft: conf::Contract = vars["nft"].clone().try_into().expect("nft");
+        assert_eq!(nft.features, vec![String::from(ckb::MULTI_SERIES)]);
+        update(&path, vec![("nft", contract())], vec![]).expect("update");
+        let vars: toml::Value = toml::from_str(&std::fs::read_to_string(&path).expect("read config")).expect("parse config");
+        assert_eq!(vars["nft"]["features"][0].as_str(), Some(ckb::MULTI_SERIES));
+
+        // an empty luacodes list keeps the previous one
+        update(&path, vec![], vec![]).expect("update");
+        let vars: toml::Value = toml::from_str(&std::fs::read_to_string(&path).expect("read config")).expect("parse config");
+        assert_eq!(vars["luacodes"][0]["tx_hash"].as_str(), Some("44"));
+
+        // an unreadable file is an error and is left as it is
+        std::fs::write(&path, [0xffu8, 0xfe, 0xfd]).expect("write config");
+        assert_eq!(update(&path, vec![("nft", contract())], vec![]).unwrap_err().code(), 900);
+        assert_eq!(std::fs::read(&path).expect("read config"), vec![0xffu8, 0xfe, 0xfd]);
+
+        // a missing file is created
+        std::fs::remove_file(&path).expect("remove config");
+        update(&path, vec![("nft", contract())], vec![]).expect("update");
+        assert!(std::fs::read_to_string(&path).expect("read config").contains("[nft]"));
+        std::fs::remove_file(&path).expect("remove config");
+    }
 }
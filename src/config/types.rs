@@ -1,4 +1,7 @@
-use serde::Deserialize;
+use serde::{
+    Deserialize, Serialize
+};
+use ckb_jsonrpc_types::ScriptHashType;
 
 #[derive(Deserialize)]
 pub struct Common {
@@ -8,39 +11,80 @@ pub struct Common {
     pub user_privkey:     String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Contract {
     pub tx_hash:   String,
     pub code_hash: String,
+    #[serde(default)]
+    pub hash_type: ScriptHashType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_args: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features:  Vec<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Luacode {
     pub tx_hash:   String,
     pub data_hash: String,
 }
 
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Devnet,
+}
+
+#[derive(Deserialize)]
+pub struct Genesis {
+    pub network:    Network,
+    pub spec_path:  Option<String>,
+    pub cache_path: Option<String>,
+}
+
+impl Default for Genesis {
+    fn default() -> Self {
+        Genesis {
+            network:    Network::Devnet,
+            spec_path:  None,
+            cache_path: None,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Vars {
     pub common:   Common,
+    #[serde(default)]
+    pub genesis:  Genesis,
     pub nft:      Contract,
     pub wallet:   Contract,
     pub payment:  Contract,
     pub kabletop: Contract,
+    #[serde(default)]
+    pub sale:     Option<Contract>,
 	pub luacodes: Vec<Luacode>
 }
 
 // ckb types format from string format "Kabletop.toml" config file
 pub mod ckb {
-    use std::convert::From;
+    use std::{
+        convert::From, path::PathBuf
+    };
     use crate::{
-        config::types as conf, ckb::transaction::helper
+        config::types as conf, ckb::transaction::helper, error::{
+            Result, KabletopError
+        }
     };
     use ckb_crypto::secp::{
 		Privkey, Generator
 	};
     use ckb_types::{
-        packed::Byte32, H256, prelude::Pack
+        packed::{
+            Byte32, Script
+        }, core::ScriptHashType, H256, prelude::Pack
     };
 
     pub struct Keypair {
@@ -55,9 +99,51 @@ pub mod ckb {
         pub user_key:        Keypair
     }
 
+    // optional behaviours which only some versions of a contract support, they are listed in "features" of the
+    // contract entry by whoever deployed it, because the sdk can't tell them from code_hash
+    pub const PURCHASE_REFUND: &str = "purchase_refund";
+    pub const COMMIT_REVEAL: &str = "commit_reveal";
+    pub const STORE_CLOSE: &str = "store_close";
+    pub const MULTI_SERIES: &str = "multi_series";
+    pub const CONFIG_V2: &str = "config_v2";
+
     pub struct Contract {
         pub tx_hash:   Byte32,
-        pub code_hash: Byte32
+        pub code_hash: Byte32,
+        pub hash_type: ScriptHashType,
+        pub type_id:   Option<Script>,
+        pub features:  Vec<String>
+    }
+
+    impl Contract {
+        // whether [feature] is declared as supported by the deployed contract
+        pub fn supports(&self, feature: &str) -> bool {
+            self.features.iter().any(|supported| supported == feature)
+        }
+
+        // the same as supports but returns FeatureMissing with contract [name] if [feature] isn't declared
+        pub fn require(&self, name: &'static str, feature: &'static str) -> Result<()> {
+            if !self.supports(feature) {
+                return Err(KabletopError::FeatureMissing { contract: name, feature });
+            }
+            Ok(())
+        }
+
+        // check [hash_type] against type-id, a contract deployed with type-id must be referenced by hash_type "type"
+        // and its code_hash must be the script hash of the type-id script, otherwise scripts built from it never run
+        pub fn check(&self, name: &str) -> Result<()> {
+            let error = |reason: &str| Err(KabletopError::Config(format!("contract {}: {}", name, reason)));
+            match (self.hash_type, &self.type_id) {
+                (ScriptHashType::Type, Some(type_id)) if type_id.calc_script_hash() != self.code_hash => {
+                    error("code_hash isn't the script hash of type-id")
+                },
+                (ScriptHashType::Type, None) => error("hash_type \"type\" requires type_args"),
+                (ScriptHashType::Data, Some(_)) | (ScriptHashType::Data1, Some(_)) => {
+                    error("type_args requires hash_type \"type\"")
+                },
+                _ => Ok(())
+            }
+        }
     }
 
     pub struct Luacode {
@@ -65,15 +151,42 @@ pub mod ckb {
         pub data_hash: Byte32
     }
 
+    // where to load the genesis info of system scripts from
+    pub enum GenesisSource {
+        Mainnet,
+        Testnet,
+        ChainSpec(PathBuf),
+        Cached(PathBuf),
+        Node
+    }
+
     pub struct Vars {
         pub common:   Common,
+        pub genesis:  GenesisSource,
         pub nft:      Contract,
         pub wallet:   Contract,
         pub payment:  Contract,
         pub kabletop: Contract,
+        pub sale:     Option<Contract>,
 		pub luacodes: Vec<Luacode>
     }
 
+    impl Vars {
+        // check every configured contract, see Contract::check
+        pub fn check(&self) -> Result<()> {
+            let contracts = vec![
+                ("nft", Some(&self.nft)), ("wallet", Some(&self.wallet)), ("payment", Some(&self.payment)),
+                ("kabletop", Some(&self.kabletop)), ("sale", self.sale.as_ref())
+            ];
+            for (name, contract) in contracts {
+                if let Some(contract) = contract {
+                    contract.check(name)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
     fn privkey_to_keypair(privkey: &str) -> Keypair {
         let privkey = {
             let byte32 = helper::blake256_to_byte32(privkey).expect("blake2b_256 to [u8; 32]");
@@ -93,7 +206,10 @@ pub mod ckb {
 			};
 			let default_contract = || Contract {
 				tx_hash:   [0u8; 32].pack(),
-				code_hash: [0u8; 32].pack()
+				code_hash: [0u8; 32].pack(),
+				hash_type: ScriptHashType::Data,
+				type_id:   None,
+				features:  vec![]
 			};
 			Vars {
 				common: Common {
@@ -102,10 +218,12 @@ pub mod ckb {
 					composer_key:    default_key(),
 					user_key:        default_key()
 				},
+				genesis:  GenesisSource::Node,
 				nft:      default_contract(),
 				wallet:   default_contract(),
 				payment:  default_contract(),
 				kabletop: default_contract(),
+				sale:     None,
 				luacodes: vec![]
 			}
 		}
@@ -115,7 +233,12 @@ pub mod ckb {
         fn from(conf_vars: conf::Vars) -> Self {
             let contract = |conf_contract: conf::Contract| Contract {
                 tx_hash:   Byte32::new(helper::blake256_to_byte32(conf_contract.tx_hash.as_str()).unwrap()),
-                code_hash: Byte32::new(helper::blake256_to_byte32(conf_contract.code_hash.as_str()).unwrap())
+                code_hash: Byte32::new(helper::blake256_to_byte32(conf_contract.code_hash.as_str()).unwrap()),
+                hash_type: conf_contract.hash_type.into(),
+                type_id:   conf_contract.type_args.map(|args| {
+                    helper::type_id_script(helper::blake256_to_byte32(args.as_str()).unwrap().to_vec())
+                }),
+                features:  conf_contract.features
             };
             let luacodes = |conf_luacodes: Vec<conf::Luacode>| -> Vec<Luacode> {
 				conf_luacodes
@@ -132,12 +255,27 @@ pub mod ckb {
                 composer_key:    privkey_to_keypair(conf_common.composer_privkey.as_str()),
                 user_key:        privkey_to_keypair(conf_common.user_privkey.as_str()),
             };
+            let genesis = |conf_genesis: conf::Genesis| match conf_genesis.network {
+                conf::Network::Mainnet => GenesisSource::Mainnet,
+                conf::Network::Testnet => GenesisSource::Testnet,
+                conf::Network::Devnet  => {
+                    if let Some(spec_path) = conf_genesis.spec_path {
+                        GenesisSource::ChainSpec(PathBuf::from(spec_path))
+                    } else if let Some(cache_path) = conf_genesis.cache_path {
+                        GenesisSource::Cached(PathBuf::from(cache_path))
+                    } else {
+                        GenesisSource::Node
+                    }
+                }
+            };
             Vars {
                 common:   common(conf_vars.common),
+                genesis:  genesis(conf_vars.genesis),
                 nft:      contract(conf_vars.nft),
                 wallet:   contract(conf_vars.wallet),
                 payment:  contract(conf_vars.payment),
                 kabletop: contract(conf_vars.kabletop),
+                sale:     conf_vars.sale.map(contract),
 				luacodes: luacodes(conf_vars.luacodes)
             }
         }
@@ -0,0 +1,209 @@
+use thiserror::Error;
+use std::result;
+
+pub type Result<T, E = KabletopError> = result::Result<T, E>;
+
+// all errors returned from sdk, and each variant has a stable error code (see [KabletopError::code]) which helps
+// game UIs or FFI callers to distinguish errors without string matching
+#[derive(Error, Debug)]
+pub enum KabletopError {
+    // config and environment
+    #[error("config error: {0}")]
+    Config(String),
+    #[error("genesis error: {0}")]
+    Genesis(String),
+    #[error("contract not found: {0}")]
+    ContractNotFound(String),
+    #[error("contract isn't deployed with type-id")]
+    ContractWithoutTypeId,
+    #[error("{contract} contract doesn't declare feature \"{feature}\"")]
+    FeatureMissing { contract: &'static str, feature: &'static str },
+    #[error("{0} is forbidden on mainnet")]
+    MainnetForbidden(&'static str),
+
+    // ckb node and indexer
+    #[error("rpc error: {0}")]
+    Rpc(String),
+    #[error("transaction {0} isn't committed")]
+    TransactionNotCommitted(String),
+    #[error("transaction {0} rejected")]
+    TransactionRejected(String),
+    #[error("cell not found: {0}")]
+    CellNotFound(String),
+    #[error("invalid transaction: {0}")]
+    InvalidTransaction(String),
+    #[error("required capacity {required} is more than available capacity {available} (in shannons)")]
+    InsufficientCapacity { required: u64, available: u64 },
+    #[error("block {0} isn't reached")]
+    BlockNotReached(String),
+
+    // nft composer and store
+    #[error("composer hasn't composed any NFTs yet")]
+    ComposerNotFound,
+    #[error("user has already created this NFT store")]
+    StoreAlreadyExists,
+    #[error("user hasn't owned a NFT store")]
+    StoreNotFound,
+    #[error("NFT store is currently on reveal status")]
+    StorePendingReveal,
+    #[error("user hasn't owned a NFT payment certificate")]
+    PaymentNotFound,
+    #[error("all owned nft cells cannot cover required nfts ({0} left)")]
+    InsufficientNfts(usize),
+    #[error("nft purchase can't be cancelled: {0}")]
+    PurchaseNotCancellable(String),
+    #[error(transparent)]
+    NftConfig(#[from] NftConfigError),
+    #[error("invalid nft reveal: {0}")]
+    InvalidReveal(String),
+    #[error("reveal secret mismatched with the commitment in payment cell")]
+    CommitmentMismatch,
+    #[error("nft swap mismatched: {field}")]
+    SwapMismatch { field: &'static str },
+    #[error("invalid nft metadata: {0}")]
+    NftMetadata(String),
+    #[error("NFT store holds {0} shannons of revenue which is too little for a cell, composer should withdraw it first")]
+    UnwithdrawnRevenue(u64),
+
+    // kabletop channel
+    #[error("channel with specified channel_script is non-existent")]
+    ChannelNotFound,
+    #[error("kabletop args mismatched: {field}")]
+    ArgsMismatch { field: &'static str },
+    #[error("number of nft beyond specified deck size")]
+    DeckSizeExceeded,
+    #[error("some of two users haven't supplied correct nft cells")]
+    NftCellsMismatch,
+    #[error("kabletop rounds is empty")]
+    EmptyRounds,
+    #[error("one challenger can't challenge twice")]
+    DuplicateChallenge,
+    #[error("winner must be 1 or 2, but got {0}")]
+    InvalidWinner(u8),
+    #[error("broken channel: {0}")]
+    BrokenChannel(&'static str),
+
+    // p2p
+    #[error("p2p error: {0}")]
+    P2p(String),
+    #[error("error from remote: {0}")]
+    Remote(String),
+
+    // wrapped errors from dependencies
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Hex(#[from] hex::FromHexError),
+    #[error(transparent)]
+    Molecule(#[from] molecule::error::VerificationError),
+    #[error(transparent)]
+    Capacity(#[from] ckb_occupied_capacity::Error),
+    #[error(transparent)]
+    Secp(#[from] ckb_crypto::secp::Error),
+}
+
+impl KabletopError {
+    // the stable error code, which groups by hundreds as config(1xx), rpc(2xx), nft(3xx), channel(4xx), p2p(5xx)
+    // and wrapped(9xx), new variants should only take new codes and never reuse old ones
+    pub fn code(&self) -> u32 {
+        match self {
+            KabletopError::Config(_)                    => 100,
+            KabletopError::Genesis(_)                   => 101,
+            KabletopError::ContractNotFound(_)          => 102,
+            KabletopError::ContractWithoutTypeId        => 103,
+            KabletopError::FeatureMissing { .. }        => 104,
+            KabletopError::MainnetForbidden(_)          => 105,
+            KabletopError::Rpc(_)                       => 200,
+            KabletopError::TransactionNotCommitted(_)   => 201,
+            KabletopError::TransactionRejected(_)       => 202,
+            KabletopError::CellNotFound(_)              => 203,
+            KabletopError::InvalidTransaction(_)        => 204,
+            KabletopError::InsufficientCapacity { .. }  => 205,
+            KabletopError::BlockNotReached(_)           => 206,
+            KabletopError::ComposerNotFound             => 300,
+            KabletopError::StoreAlreadyExists           => 301,
+            KabletopError::StoreNotFound                => 302,
+            KabletopError::StorePendingReveal           => 303,
+            KabletopError::PaymentNotFound              => 304,
+            KabletopError::InsufficientNfts(_)          => 305,
+            KabletopError::PurchaseNotCancellable(_)    => 306,
+            KabletopError::NftConfig(_)                 => 307,
+            KabletopError::InvalidReveal(_)             => 308,
+            KabletopError::CommitmentMismatch           => 309,
+            KabletopError::SwapMismatch { .. }          => 310,
+            KabletopError::NftMetadata(_)               => 311,
+            KabletopError::UnwithdrawnRevenue(_)        => 312,
+            KabletopError::ChannelNotFound              => 400,
+            KabletopError::ArgsMismatch { .. }          => 401,
+            KabletopError::DeckSizeExceeded             => 402,
+            KabletopError::NftCellsMismatch             => 403,
+            KabletopError::EmptyRounds                  => 404,
+            KabletopError::DuplicateChallenge           => 405,
+            KabletopError::InvalidWinner(_)             => 406,
+            KabletopError::BrokenChannel(_)             => 407,
+            KabletopError::P2p(_)                       => 500,
+            KabletopError::Remote(_)                    => 501,
+            KabletopError::Io(_)                        => 900,
+            KabletopError::Json(_)                      => 901,
+            KabletopError::Hex(_)                       => 902,
+            KabletopError::Molecule(_)                  => 903,
+            KabletopError::Capacity(_)                  => 904,
+            KabletopError::Secp(_)                      => 905,
+        }
+    }
+}
+
+// errors from constructing or parsing a NFTConfig
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum NftConfigError {
+    #[error("package price and package capacity must be greater than 0")]
+    InvalidPackage,
+    #[error("package price {0} overflows for 255 packages")]
+    PriceOverflow(u64),
+    #[error("nft config table is empty")]
+    EmptyTable,
+    #[error("nft config data is truncated in header ({0} bytes)")]
+    TruncatedHeader(usize),
+    #[error("unsupported nft config version {0}")]
+    UnsupportedVersion(u8),
+    #[error("rates in nft config table aren't in ASC order at #{0}")]
+    UnsortedRates(usize),
+    #[error("duplicate nft {} in nft config table", hex::encode(.0))]
+    DuplicateNft([u8; 20]),
+    #[error("{0} trailing bytes after nft config table")]
+    TrailingBytes(usize),
+    #[error("total weight of nfts is 0")]
+    ZeroWeight,
+    #[error("{0} nfts of nonzero weight can't each get a lottery value")]
+    TooManyWeights(usize)
+}
+
+impl From<toml::de::Error> for KabletopError {
+    fn from(err: toml::de::Error) -> Self {
+        KabletopError::Config(err.to_string())
+    }
+}
+
+impl From<toml::ser::Error> for KabletopError {
+    fn from(err: toml::ser::Error) -> Self {
+        KabletopError::Config(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::KabletopError;
+
+    #[test]
+    fn test_stable_error_codes() {
+        assert_eq!(KabletopError::StoreNotFound.code(), 302);
+        assert_eq!(KabletopError::InsufficientCapacity { required: 2, available: 1 }.code(), 205);
+        assert_eq!(KabletopError::ArgsMismatch { field: "user_deck_size" }.code(), 401);
+        let err: KabletopError = hex::decode("0").unwrap_err().into();
+        assert_eq!(err.code(), 902);
+        let err: KabletopError = toml::from_str::<toml::Value>("=").unwrap_err().into();
+        assert_eq!(err.code(), 100);
+    }
+}
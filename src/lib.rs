@@ -3,6 +3,7 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod error;
 pub mod config;
 pub mod ckb;
 pub mod p2p;
@@ -7,8 +7,8 @@ use serde_json::{
 use serde::{
 	Serialize, de::DeserializeOwned
 };
-use anyhow::{
-    Result, anyhow
+use crate::error::{
+	Result, KabletopError
 };
 use std::{
 	collections::HashMap, thread, net::TcpStream, time::{
@@ -52,7 +52,7 @@ fn server_send(msg: OwnedMessage) {
 			ok = false;
 		}
 		if let Err(err) = server.send_message(&msg) {
-			println!("clien send error => {}", err);
+			tracing::warn!(%err, "send message to server failed");
 			ok = false;
 		}
 	}
@@ -108,7 +108,10 @@ impl Client {
 		where
 			F: Fn() + Send + Sync + 'static
 	{
-		let client = ClientBuilder::new(self.socket.as_str())?.connect_insecure()?;
+		let client = ClientBuilder::new(self.socket.as_str())
+			.map_err(|err| KabletopError::P2p(err.to_string()))?
+			.connect_insecure()
+			.map_err(|err| KabletopError::P2p(err.to_string()))?;
 		let (mut stream, sink) = client.split()?;
 		*SERVER.write().unwrap() = Some(sink);
 		*CALLBACK.write().unwrap() = Some(Box::new(local_callback));
@@ -120,13 +123,16 @@ impl Client {
 			server_receiver.insert(name.clone(), sr);
 		}
 		let (writer, reader) = channel();
+		let span = tracing::info_span!("p2p_client", socket = %self.socket);
 		// start client write thread
+		let write_span = span.clone();
 		thread::spawn(move || {
+			let _span = write_span.entered();
 			let sleep_ms = sleep_ms.clone();
 			update_heartbeat(Some(SystemTime::now()), Some(SystemTime::now()));
 			loop {
 				if SERVER.read().unwrap().is_none() {
-					println!("p2p client thread CLOSED");
+					tracing::debug!("p2p client WRITE thread closed");
 					return
 				}
 				let (last_ping, last_pong) = *HEARTBEAT.read().unwrap();
@@ -152,16 +158,17 @@ impl Client {
 		});
 		// start client read thread
 		thread::spawn(move || {
+			let _span = span.entered();
 			let sleep_ms = sleep_ms.clone();
 			loop {
 				if SERVER.read().unwrap().is_none() {
-					println!("p2p client WORKER thread CLOSED");
+					tracing::debug!("p2p client READ thread closed");
 					return
 				}
 				// receiving response and calling messages from server, client's recv_messsage method will be blocked
 				let recv = stream.recv_message();
 				if let Err(err) = recv {
-					println!("client error => {:?}", err);
+					tracing::warn!(%err, "receive message from server failed");
 					close_server();
 					continue
 				}
@@ -174,6 +181,7 @@ impl Client {
 						match message {
 							Wrapper::Send(payload) => {
 								// check wether message is in the client registry table
+								tracing::debug!(method = %payload.name, "request from server");
 								if let Some(function) = self.client_registry.get(&payload.name) {
 									let params = from_str(payload.body.as_str()).unwrap();
 									let future = function(0, params);
@@ -231,12 +239,13 @@ impl ClientSender {
 
 	pub fn shutdown(&self) {
 		if let Err(err) = self.writer.send(String::from("_SHUTDOWN_")) {
-			println!("[WARN] shutdown error: {}", err);
+			tracing::warn!(%err, "shutdown client failed");
 		}
 	}
 }
 
 impl Caller for ClientSender {
+	#[tracing::instrument(level = "debug", skip_all, fields(method = name))]
 	fn call<T: Serialize, R: DeserializeOwned>(&self, name: &str, params: T) -> Result<R> {
 		if let Some(response) = self.server_response.get(&String::from(name)) {
 			let request = to_string(
@@ -247,17 +256,18 @@ impl Caller for ClientSender {
 					}
 				)
 			)?;
-			self.writer.send(request)?;
+			self.writer.send(request).map_err(|err| KabletopError::P2p(err.to_string()))?;
 			let value: R = {
-				let value: Value = from_str(response.recv()?.as_str())?;
+				let response = response.recv().map_err(|err| KabletopError::P2p(err.to_string()))?;
+				let value: Value = from_str(response.as_str())?;
 				if let Ok(error) = from_value::<Error>(value.clone()) {
-					return Err(anyhow!("error from server: {}", error.reason));
+					return Err(KabletopError::Remote(error.reason));
 				}
 				from_value(value)?
 			};
 			Ok(value)
 		} else {
-			Err(anyhow!("method {} isn't registered", name))
+			Err(KabletopError::P2p(format!("method {} isn't registered", name)))
 		}
 	}
 }
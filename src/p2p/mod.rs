@@ -1,7 +1,7 @@
 use serde::{
 	Serialize, Deserialize, de::DeserializeOwned
 };
-use anyhow::Result;
+use crate::error::Result;
 
 mod server;
 mod client;
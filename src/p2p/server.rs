@@ -16,8 +16,8 @@ use serde::{
 use serde_json::{
 	Value, from_str, from_value, to_string, json
 };
-use anyhow::{
-	Result, anyhow
+use crate::error::{
+	Result, KabletopError
 };
 use super::{
 	Wrapper, Payload, Error, Caller
@@ -73,11 +73,11 @@ fn client_send(id: i32, msg: OwnedMessage) {
 			ok = false;
 		}
 		if let Err(err) = client.send_message(&msg) {
-			println!("#{} send message error: {}", id, err.to_string());
+			tracing::warn!(client_id = id, %err, "send message to client failed");
 			ok = false;
 		}
 	} else {
-		println!("send on #{} CLOSED client", id);
+		tracing::debug!(client_id = id, "send message on closed client");
 		ok = false;
 	}
 	if !ok {
@@ -123,12 +123,15 @@ impl Server {
 		init_statics();
 		*CALLBACK.write().unwrap() = Some(Box::new(local_callback));
 		let (writer, reader) = unbounded::<(i32, String)>();
+		let span = tracing::info_span!("p2p_server", socket = %self.socket);
 		// start p2p server controller thread
+		let controller_span = span.clone();
 		thread::spawn(move || {
+			let _span = controller_span.entered();
 			let sleep_ms = sleep_ms.clone();
 			loop {
 				if *STOP.read().unwrap() {
-					println!("p2p server CONTROLLER thread closed");
+					tracing::debug!("p2p server CONTROLLER thread closed");
 					return
 				}
 				// receiving message from server controller
@@ -137,7 +140,7 @@ impl Server {
 						// send to specified serverclient
 						match SERVER_CLIENTS.write().unwrap().get_mut(&client_id) {
 							Some(serverclient) => serverclient.send(message).unwrap(),
-							None => println!("send message {} to client #{} failed", message, client_id)
+							None => tracing::warn!(client_id, %message, "send message to unknown client")
 						}
 					} else {
 						if message == String::from("_SHUTDOWN_") {
@@ -147,7 +150,7 @@ impl Server {
 						for (client_id, _) in &*CLIENTS.write().unwrap() {
 							match SERVER_CLIENTS.write().unwrap().get_mut(client_id) {
 								Some(serverclient) => serverclient.send(message.clone()).unwrap(),
-								None => println!("send message {} to client #{} failed", message, client_id)
+								None => tracing::warn!(client_id, %message, "send message to unknown client")
 							}
 						}
 					}
@@ -157,10 +160,11 @@ impl Server {
 		});
 		// start p2p server worker thread
 		thread::spawn(move || {
+			let _span = span.entered();
 			let mut client_id = 0;
 			loop {
 				if *STOP.read().unwrap() {
-					println!("p2p server WORKER thread closed");
+					tracing::debug!("p2p server WORKER thread closed");
 					return
 				}
 				// listening client connection
@@ -186,17 +190,21 @@ impl Server {
 				CLIENTS.write().unwrap().insert(client_id, Some(sink));
 				udapte_heartbeat(client_id);
 				let this_client_id = client_id;
+				let connection_span = tracing::info_span!("p2p_connection", client_id);
+				tracing::info!(client_id, "client connected");
 				// start read thread for current connection
+				let read_span = connection_span.clone();
 				thread::spawn(move || loop {
+					let _span = read_span.enter();
 					if CLIENTS.read().unwrap().get(&this_client_id).is_none()
 						|| CLIENTS.read().unwrap().get(&this_client_id).unwrap().is_none() {
-						println!("p2p serverclient #{} READ thread closed", this_client_id);
+						tracing::debug!("p2p serverclient READ thread closed");
 						return
 					}
 					// receiving calling messages from client
 					let recv = stream.recv_message();
 					if let Err(err) = recv {
-						println!("serverclient #{} next => {}", this_client_id, err);
+						tracing::warn!(%err, "receive message from client failed");
 						close_client(this_client_id);
 						continue
 					}
@@ -209,6 +217,7 @@ impl Server {
 							match message {
 								Wrapper::Send(payload) => {
 									// searching in server response registry table
+									tracing::debug!(method = %payload.name, "request from client");
 									if let Some(function) = SERVER_REGISTRY.read().unwrap().get(&payload.name) {
 										let params = from_str(payload.body.as_str()).unwrap();
 										let future = function(this_client_id, params);
@@ -250,11 +259,12 @@ impl Server {
 				});
 				// start write thread for current connection
 				thread::spawn(move || {
+					let _span = connection_span.entered();
 					let sleep_ms = sleep_ms.clone();
 					loop {
 						if CLIENTS.read().unwrap().get(&this_client_id).is_none()
 							|| CLIENTS.read().unwrap().get(&this_client_id).unwrap().is_none() {
-							println!("p2p serverclient #{} WRITE thread closed", this_client_id);
+							tracing::debug!("p2p serverclient WRITE thread closed");
 							return
 						}
 						// fetching message from server client
@@ -305,7 +315,7 @@ impl ServerClient {
 
 	pub fn shutdown(&self) {
 		if let Err(err) = self.writer.send((0, String::from("_SHUTDOWN_"))) {
-			println!("[WRAN] shutdown error: {}", err);
+			tracing::warn!(%err, "shutdown server failed");
 		}
 	}
 
@@ -316,12 +326,13 @@ impl ServerClient {
 }
 
 impl Caller for ServerClient {
+	#[tracing::instrument(level = "debug", skip_all, fields(client_id = self.client_id, method = name))]
 	fn call<T: Serialize, R: DeserializeOwned>(&self, name: &str, params: T) -> Result<R> {
 		if !self.active() {
-			return Err(anyhow!("no client connected"));
+			return Err(KabletopError::P2p(String::from("no client connected")));
 		}
 		if self.client_id == 0 {
-			return Err(anyhow!("empty client_id"));
+			return Err(KabletopError::P2p(String::from("empty client_id")));
 		}
 		if let Some(receivers) = RESPONSE_RECEIVERS.read().unwrap().get(&self.client_id) {
 			if let Some(receiver) = receivers.get(&String::from(name)) {
@@ -333,20 +344,21 @@ impl Caller for ServerClient {
 						}
 					)
 				)?;
-				self.writer.send((self.client_id, request))?;
+				self.writer.send((self.client_id, request)).map_err(|err| KabletopError::P2p(err.to_string()))?;
 				let value: R = {
-					let response: Value = from_str(receiver.recv()?.as_str())?;
+					let response = receiver.recv().map_err(|err| KabletopError::P2p(err.to_string()))?;
+					let response: Value = from_str(response.as_str())?;
 					if let Ok(error) = from_value::<Error>(response.clone()) {
-						return Err(anyhow!("error from server: {}", error.reason));
+						return Err(KabletopError::Remote(error.reason));
 					}
 					from_value(response)?
 				};
 				Ok(value)
 			} else {
-				Err(anyhow!("method {} isn't registered", name))
+				Err(KabletopError::P2p(format!("method {} isn't registered", name)))
 			}
 		} else {
-			Err(anyhow!("no client id #{}", self.client_id))
+			Err(KabletopError::P2p(format!("no client id #{}", self.client_id)))
 		}
 	}
 }